@@ -0,0 +1,146 @@
+use cgmath::{Deg, EuclideanSpace, InnerSpace, Matrix4, Point3, Quaternion, Rotation, SquareMatrix, Vector3,
+             Vector4, perspective};
+use Ray;
+
+/**
+ * A perspective camera looking down its local -z axis.
+ */
+pub struct Camera {
+    pub position: Point3<f32>,
+    // The rotation from camera space into world space.
+    pub orientation: Quaternion<f32>,
+    // The vertical field of view.
+    pub fov: Deg<f32>,
+    // The width of the viewport over its height.
+    pub aspect: f32,
+    pub near: f32,
+    pub far: f32,
+}
+
+impl Camera {
+    pub fn new(position: Point3<f32>, orientation: Quaternion<f32>, fov: Deg<f32>, aspect: f32) -> Camera {
+        Camera {
+            position: position,
+            orientation: orientation,
+            fov: fov,
+            aspect: aspect,
+            near: 0.1,
+            far: 1000.0,
+        }
+    }
+
+    /**
+     * Rotates the camera around the world origin, keeping its distance, e.g. for a track-ball.
+     */
+    pub fn orbit(&mut self, rotation: Quaternion<f32>) {
+        self.position = Point3::from_vec(rotation.rotate_vector(self.position.to_vec()));
+        self.orientation = (rotation * self.orientation).normalize();
+    }
+
+    pub fn view_matrix(&self) -> Matrix4<f32> {
+        Matrix4::from(self.orientation.invert()) * Matrix4::<f32>::from_translation(Point3::origin() - self.position)
+    }
+
+    pub fn projection_matrix(&self) -> Matrix4<f32> {
+        perspective(self.fov, self.aspect, self.near, self.far)
+    }
+
+    /**
+     * Unprojects a screen position into a ray in world space.
+     *
+     * @param x The horizontal pixel position, from the left of the viewport.
+     * @param y The vertical pixel position, from the top of the viewport.
+     * @param viewport The width and height of the viewport in pixels.
+     */
+    pub fn screen_to_world(&self, x: f64, y: f64, viewport: (u32, u32)) -> Ray {
+        let inverse = (self.projection_matrix() * self.view_matrix())
+            .invert()
+            .expect("View projection should be invertible");
+
+        let ndc_x = (2.0 * x / viewport.0 as f64 - 1.0) as f32;
+        let ndc_y = (1.0 - 2.0 * y / viewport.1 as f64) as f32;
+
+        let unproject = |ndc_z: f32| {
+            let point = inverse * Vector4::new(ndc_x, ndc_y, ndc_z, 1.0);
+            Point3::new((point.x / point.w) as f64, (point.y / point.w) as f64, (point.z / point.w) as f64)
+        };
+        let near = unproject(-1.0);
+        let far = unproject(1.0);
+
+        let direction: Vector3<f64> = far - near;
+        Ray {
+            origin: near,
+            direction: direction,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{One, Rotation3};
+
+    fn assert_near(a: Vector4<f32>, b: Vector4<f32>) {
+        assert!((a - b).magnitude() < 1e-5, "{:?} != {:?}", a, b);
+    }
+
+    fn camera() -> Camera {
+        Camera::new(Point3::new(0.0, 0.0, 5.0), Quaternion::one(), Deg(90.0), 2.0)
+    }
+
+    #[test]
+    fn view_matrix_moves_the_camera_to_the_origin() {
+        let view = camera().view_matrix();
+
+        assert_near(view * Vector4::new(0.0, 0.0, 5.0, 1.0), Vector4::new(0.0, 0.0, 0.0, 1.0));
+        assert_near(view * Vector4::new(0.0, 0.0, 0.0, 1.0), Vector4::new(0.0, 0.0, -5.0, 1.0));
+        assert_near(view * Vector4::new(1.0, 2.0, 0.0, 1.0), Vector4::new(1.0, 2.0, -5.0, 1.0));
+    }
+
+    #[test]
+    fn view_matrix_of_a_rotated_camera_looks_along_its_orientation() {
+        // Turned a quarter to the left the camera looks along -x.
+        let camera = Camera::new(Point3::new(0.0, 0.0, 0.0),
+                                 Quaternion::from_angle_y(Deg(90.0)),
+                                 Deg(90.0),
+                                 1.0);
+
+        assert_near(camera.view_matrix() * Vector4::new(-3.0, 0.0, 0.0, 1.0), Vector4::new(0.0, 0.0, -3.0, 1.0));
+    }
+
+    #[test]
+    fn projection_matrix_maps_the_frustum_to_clip_space() {
+        let camera = camera();
+        let projection = camera.projection_matrix();
+        let ndc = |point: Vector4<f32>| {
+            let clip = projection * point;
+            Vector4::new(clip.x / clip.w, clip.y / clip.w, clip.z / clip.w, 1.0)
+        };
+
+        assert_near(ndc(Vector4::new(0.0, 0.0, -camera.near, 1.0)), Vector4::new(0.0, 0.0, -1.0, 1.0));
+        assert_near(ndc(Vector4::new(0.0, 0.0, -camera.far, 1.0)), Vector4::new(0.0, 0.0, 1.0, 1.0));
+        // The 90 degree vertical field of view spans as far up as it is deep, widened by the aspect.
+        assert_near(ndc(Vector4::new(0.0, 4.0, -4.0, 1.0)), ndc(Vector4::new(0.0, 0.0, -4.0, 1.0)) +
+                    Vector4::new(0.0, 1.0, 0.0, 0.0));
+        assert_near(ndc(Vector4::new(8.0, 0.0, -4.0, 1.0)), ndc(Vector4::new(0.0, 0.0, -4.0, 1.0)) +
+                    Vector4::new(1.0, 0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn orbit_keeps_the_origin_in_view() {
+        let mut camera = camera();
+        camera.orbit(Quaternion::from_angle_y(Deg(90.0)));
+
+        assert!((camera.position - Point3::new(5.0, 0.0, 0.0)).magnitude() < 1e-5);
+        assert_near(camera.view_matrix() * Vector4::new(0.0, 0.0, 0.0, 1.0), Vector4::new(0.0, 0.0, -5.0, 1.0));
+    }
+
+    #[test]
+    fn screen_to_world_through_the_center_looks_forward() {
+        let ray = camera().screen_to_world(400.0, 300.0, (800, 600));
+
+        assert!((ray.origin - Point3::new(0.0, 0.0, 4.9)).magnitude() < 1e-4);
+        let direction = ray.direction.normalize();
+        assert!((direction - Vector3::new(0.0, 0.0, -1.0)).magnitude() < 1e-4);
+    }
+}
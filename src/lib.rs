@@ -1,194 +1,6326 @@
+// Fields are initialized explicitly, e.g. `normal: normal`, throughout.
+#![allow(clippy::redundant_field_names)]
+
 extern crate delaunay2d;
 extern crate cgmath;
 extern crate noise;
 extern crate rand;
 
-use cgmath::{InnerSpace, Point3, Vector3, Zero};
+pub mod camera;
+
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, Vector2, Vector3, Vector4, Zero};
 use delaunay2d::{Delaunay2D};
 use noise::{Fbm, NoiseModule, Seedable};
-use rand::Rng;
-use std::collections::HashMap;
+use rand::{Rng, SeedableRng, XorShiftRng};
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use std::io::{self, Write};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/** The range noise heights are normalized to before any shaping (bands, feedback, etc.). */
+pub const NOISE_RANGE: (f64, f64) = (-1.0, 1.0);
 
 pub struct VoronoiTerrain {
     pub terrain_graph: Graph<TerrainVertex>,
 
     pub region_graph: Graph<Region>,
 
+    // The site which generated each region, indexed by region.
+    pub site_of_region: Vec<(f64, f64)>,
+
     pub water_level: u32,
+
+    // The height range which the water level is relative to.
+    pub height: u32,
+
+    // The height reported for positions outside of the terrain, if any.
+    pub out_of_bounds_height: Option<f64>,
+
+    // The neighbouring regions of each region, built on first use.
+    region_adjacency: OnceLock<Vec<Vec<usize>>>,
+
+    // The regions bounded by each terrain vertex, built on first use.
+    regions_by_vertex: OnceLock<Vec<Vec<usize>>>,
 }
 
 impl VoronoiTerrain {
     pub fn builder() -> VoronoiTerrainBuilder {
-        return VoronoiTerrainBuilder::new();
+        VoronoiTerrainBuilder::new()
     }
-}
 
-pub struct VoronoiTerrainBuilder {
-    seed: usize,
-    water_level: u32,
-    height: u32,
-    sites: Vec<(f64, f64)>,
-}
+    /**
+     * Calculates a sphere enclosing all terrain vertices using Ritter's algorithm.
+     *
+     * The result isn't the minimal bounding sphere but is typically within ~5% of it.
+     *
+     * @return The center and radius of the sphere.
+     */
+    pub fn bounding_sphere(&self) -> (Point3<f64>, f64) {
+        let vertices = &self.terrain_graph.vertices;
+        if vertices.is_empty() {
+            return (Point3::origin(), 0.0);
+        }
 
-impl VoronoiTerrainBuilder {
-    fn new() -> VoronoiTerrainBuilder {
-        let mut rng = rand::thread_rng();
-        VoronoiTerrainBuilder {
-            seed: rng.gen::<usize>(),
-            water_level: 50,
-            height: 100,
-            sites: Vec::new(),
+        let farthest_from = |point: Point3<f64>| -> Point3<f64> {
+            vertices.iter()
+                .map(|vertex| vertex.position)
+                .fold(point, |farthest, position| {
+                    if (position - point).magnitude2() > (farthest - point).magnitude2() {
+                        position
+                    } else {
+                        farthest
+                    }
+                })
+        };
+
+        // Seed the sphere with an approximately diametric pair of vertices.
+        let p1 = farthest_from(vertices[0].position);
+        let p2 = farthest_from(p1);
+
+        let mut center = p1 + (p2 - p1) * 0.5;
+        let mut radius = (p2 - p1).magnitude() * 0.5;
+
+        // Grow the sphere to include any vertices which lie outside of it.
+        for vertex in vertices.iter() {
+            let offset = vertex.position - center;
+            let distance = offset.magnitude();
+            if distance > radius {
+                let new_radius = (radius + distance) * 0.5;
+                center += offset * ((new_radius - radius) / distance);
+                radius = new_radius;
+            }
         }
+
+        (center, radius)
     }
 
-    pub fn set_seed(&mut self, seed: usize) -> &mut VoronoiTerrainBuilder {
-        self.seed = seed;
-        self
+    /**
+     * Lists regions by their number of neighbouring regions, most connected first.
+     *
+     * Useful for spotting degenerate triangulation artifacts.
+     *
+     * @return Pairs of (region index, neighbour count) sorted by descending neighbour count.
+     */
+    pub fn regions_by_degree(&self) -> Vec<(usize, usize)> {
+        let mut degrees: Vec<(usize, usize)> = self.region_adjacency()
+            .iter()
+            .map(|neighbours| neighbours.len())
+            .enumerate()
+            .collect();
+
+        // Ties are broken by region index to keep the ordering deterministic.
+        degrees.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        degrees
     }
 
-    pub fn set_sites(&mut self, sites: Vec<(f64, f64)>) -> &mut VoronoiTerrainBuilder {
-        self.sites = sites;
-        self
+    /** The neighbouring regions of a region, from the cached region adjacency. */
+    pub fn region_neighbors(&self, region: usize) -> &[usize] {
+        &self.region_adjacency()[region]
     }
 
-    pub fn set_water_level(&mut self, water_level: u32) -> &mut VoronoiTerrainBuilder {
-        self.water_level = water_level;
-        self
+    /**
+     * The neighbouring regions of each region, built from the region graph on first use and
+     * cached until invalidated.
+     */
+    pub fn region_adjacency(&self) -> &[Vec<usize>] {
+        self.region_adjacency.get_or_init(|| self.region_graph.adjacency())
     }
 
-    pub fn set_height(&mut self, height: u32) -> &mut VoronoiTerrainBuilder {
-        self.height = height;
-        self
+    /**
+     * The regions bounded by each terrain vertex, built from the graphs on first use and cached
+     * until invalidated.
+     */
+    pub fn regions_by_vertex(&self) -> &[Vec<usize>] {
+        self.regions_by_vertex.get_or_init(|| regions_by_vertex(&self.terrain_graph, &self.region_graph))
     }
 
-    pub fn build(&self) -> VoronoiTerrain {
-        // TODO(orglofch): Calculate the boundary from the sites.
-        // TODO(orglofch): Port to Fortunes algorithm.
-        let mut dt = Delaunay2D::new((0.0, 0.0), 9999.0);
+    /**
+     * Discards the cached region adjacency and regions by vertex, required after editing the
+     * edges of the region graph or the vertices of any region.
+     */
+    pub fn invalidate_caches(&mut self) {
+        self.region_adjacency = OnceLock::new();
+        self.regions_by_vertex = OnceLock::new();
+    }
+
+    /**
+     * Calculates the flow accumulation of each terrain vertex.
+     *
+     * Each vertex drains into its lowest neighbour, if that neighbour is lower than it.
+     *
+     * @return The number of upstream vertices draining through each terrain vertex.
+     */
+    pub fn flow_accumulation(&self) -> Vec<f64> {
+        let vertices = &self.terrain_graph.vertices;
+        let flow_targets = self.terrain_flow_targets();
+
+        // Visit vertices from highest to lowest so all upstream flow is known before it's passed on.
+        let mut order: Vec<usize> = (0..vertices.len()).collect();
+        order.sort_by(|&a, &b| {
+            vertices[b].position.z.partial_cmp(&vertices[a].position.z).unwrap()
+        });
 
-        for site in self.sites.iter() {
-            dt.add_point(*site);
+        let mut accumulation: Vec<f64> = vec![0.0; vertices.len()];
+        for vertex_index in order.into_iter() {
+            if let Some(target) = flow_targets[vertex_index] {
+                accumulation[target] += accumulation[vertex_index] + 1.0;
+            }
         }
+        accumulation
+    }
 
-        let (dt_vertices, dt_cells) = dt.export_voronoi_regions();
+    /**
+     * Calculates the river segments of the terrain for rendering tapered rivers.
+     *
+     * Each vertex draining into a neighbour forms a segment, its width is the square root of
+     * the flow passing along it, the vertex itself and all of its upstream vertices.
+     *
+     * @return The upstream vertex, downstream vertex and width of each segment.
+     */
+    pub fn river_segments(&self) -> Vec<(usize, usize, f64)> {
+        let accumulation = self.flow_accumulation();
+        self.terrain_flow_targets()
+            .into_iter()
+            .enumerate()
+            .filter_map(|(vertex_index, target)| {
+                target.map(|target| (vertex_index, target, (accumulation[vertex_index] + 1.0).sqrt()))
+            })
+            .collect()
+    }
 
-        let noise = Fbm::new().set_seed(self.seed);
+    /**
+     * Scores each region with a user supplied function, e.g. to rank settlement locations.
+     *
+     * @return The score of each region, indexed by region.
+     */
+    pub fn score_regions<F>(&self, f: F) -> Vec<f64>
+        where F: Fn(&Region) -> f64
+    {
+        self.region_graph.vertices.iter().map(f).collect()
+    }
 
-        // Generate the set of connecting edges for each vertex and the reverse for fast lookup.
-        let mut terrain_edges_by_vertex_index: HashMap<usize, Vec<usize>> = HashMap::with_capacity(dt_vertices.len());
-
-        // Capacity is a rough estimate of the total number of edges in the graph.
-        // The average number of edges in a voronoi cell is < 6 and each is shared between 2 cells.
-        let mut terrain_edges: Vec<(usize, usize)> = Vec::with_capacity(dt_vertices.len() * 2);
-        let mut region_edges: Vec<(usize, usize)> = Vec::with_capacity(dt_vertices.len() * 2);
-
-        // Terrain edges are near 1:1 with region edges (all except for the exterior cells).
-        // TODO(orglofc): Consider combining the edges to a common structure so one can transition between the two.
-        let mut region_by_terrain_edge: HashMap<(usize, usize), usize> = HashMap::with_capacity(dt_vertices.len() * 2);
-
-        // For fast check retrieval of existing edges.
-        let mut terrain_edge_index_by_edge: HashMap<(usize, usize), usize> = HashMap::with_capacity(dt_vertices.len() * 2);
-
-        for (region_index, dt_cell) in dt_cells.iter().enumerate() {
-            for (vertex_index, current_vertex_index) in dt_cell.iter().enumerate() {
-                let next_vertex_index = dt_cell[(vertex_index + 1) % dt_cell.len()];
-
-                // If the reverse edge already exists then we can skip adding this as a new edge.
-                // The winding order will guarantee the duplicate edge is the reverse of the current edge.
-                // We can also use the knowledge of when duplicates occur to mark region edges.
-                let reverse_edge = (next_vertex_index, *current_vertex_index);
-
-                let edge_index = match terrain_edge_index_by_edge.get(&reverse_edge) {
-                    Some(&edge_index) => {
-                        // Insert a new region edge.
-                        let other_region = region_by_terrain_edge.get(&reverse_edge)
-                            .expect("The reverse index should exist for the region index");
-                        region_edges.push((region_index, *other_region));
-                        edge_index
-                    },
-                    None => {
-                        // Insert the new terrain edge.
-                        let edge = (*current_vertex_index, next_vertex_index);
-                        region_by_terrain_edge.insert(edge, region_index);
-                        terrain_edge_index_by_edge.insert(edge, *current_vertex_index);
-                        terrain_edges.push(edge);
-                        terrain_edges.len() - 1
-                    }
-                };
+    /**
+     * Calculates the planar area of each region, indexed by region.
+     *
+     * Regions are clipped to the bounds of the sites, as the outer regions reach far beyond them.
+     */
+    pub fn region_areas(&self) -> Vec<f64> {
+        let bounds = self.site_bounds();
+        (0..self.region_graph.vertices.len())
+            .map(|region| polygon_area(&clip_polygon_to_bounds(&self.region_polygon(region), bounds)))
+            .collect()
+    }
+
+    /**
+     * Calculates the Gabriel graph of the sites.
+     *
+     * Two sites are connected if the circle with their connecting edge as its diameter contains
+     * no other site. The Gabriel graph is a subgraph of the delaunay triangulation.
+     *
+     * @return Pairs of region indices, with the lower index first.
+     */
+    pub fn gabriel_edges(&self) -> Vec<(usize, usize)> {
+        let adjacency = self.region_adjacency();
+        let sites = &self.site_of_region;
+
+        // A delaunay edge is a Gabriel edge iff none of the vertices opposite it in the adjacent
+        // triangles lie in its diametral circle, these are exactly the shared neighbours.
+        self.delaunay_edges()
+            .into_iter()
+            .filter(|&(a, b)| {
+                let center = ((sites[a].0 + sites[b].0) * 0.5, (sites[a].1 + sites[b].1) * 0.5);
+                let radius2 = distance2(sites[a], sites[b]) * 0.25;
+                adjacency[a].iter()
+                    .filter(|c| adjacency[b].contains(c))
+                    .all(|&c| distance2(sites[c], center) >= radius2)
+            })
+            .collect()
+    }
+
+    /**
+     * Calculates the relative neighbourhood graph of the sites.
+     *
+     * Two sites are connected if no third site is closer to both of them than they are to each
+     * other. The relative neighbourhood graph is a subgraph of the Gabriel graph.
+     *
+     * Each candidate edge is checked against every site so this runs in O(n * e).
+     *
+     * @return Pairs of region indices, with the lower index first.
+     */
+    pub fn relative_neighborhood_edges(&self) -> Vec<(usize, usize)> {
+        let sites = &self.site_of_region;
+
+        self.gabriel_edges()
+            .into_iter()
+            .filter(|&(a, b)| {
+                let length2 = distance2(sites[a], sites[b]);
+                (0..sites.len())
+                    .filter(|&c| c != a && c != b)
+                    .all(|c| distance2(sites[a], sites[c]).max(distance2(sites[b], sites[c])) >= length2)
+            })
+            .collect()
+    }
+
+    /**
+     * Calculates the roughness of a region as the standard deviation of its boundary
+     * vertex heights relative to its center.
+     *
+     * Flat regions, and regions without vertices, have a roughness near 0.
+     */
+    pub fn region_roughness(&self, region: usize) -> f64 {
+        let region = &self.region_graph.vertices[region];
+        let vertices = &self.terrain_graph.vertices;
+        if region.vertices.is_empty() {
+            return 0.0;
+        }
+
+        let variance = region.vertices.iter()
+            .map(|&i| {
+                let delta = vertices[i].position.z - region.center.z;
+                delta * delta
+            })
+            .sum::<f64>() / region.vertices.len() as f64;
+
+        variance.sqrt()
+    }
+
+    /**
+     * Scores the complexity of the terrain, e.g. for comparing generators.
+     *
+     * The score is the Shannon entropy, in bits, of the vertex heights quantized to the
+     * terrain's height levels, plus the mean slope of the terrain edges. Flat terrain scores zero.
+     */
+    pub fn complexity(&self) -> f64 {
+        let vertices = &self.terrain_graph.vertices;
+        if vertices.is_empty() {
+            return 0.0;
+        }
+
+        let levels = self.height.max(1) as usize;
+        let mut histogram: Vec<usize> = vec![0; levels];
+        for vertex in vertices.iter() {
+            let t = (vertex.position.z - NOISE_RANGE.0) / (NOISE_RANGE.1 - NOISE_RANGE.0);
+            let level = (t.max(0.0) * levels as f64) as usize;
+            histogram[level.min(levels - 1)] += 1;
+        }
+        let entropy = histogram.iter()
+            .filter(|&&count| count > 0)
+            .map(|&count| {
+                let p = count as f64 / vertices.len() as f64;
+                -p * p.log2()
+            })
+            .sum::<f64>();
 
-                terrain_edges_by_vertex_index.entry(*current_vertex_index)
-                    .or_insert(Vec::with_capacity(5)) // Average edges < 6.
-                    .push(edge_index);
+        // Vertical edges are skipped, their infinite slope would swamp the mean.
+        let slopes: Vec<f64> = self.terrain_graph.edges.iter()
+            .map(|&(a, b)| edge_slope(vertices[a].position, vertices[b].position))
+            .filter(|slope| slope.is_finite())
+            .collect();
+        let mean_slope = if slopes.is_empty() {
+            0.0
+        } else {
+            slopes.iter().sum::<f64>() / slopes.len() as f64
+        };
+
+        entropy + mean_slope
+    }
+
+    /**
+     * Compares this terrain against another, matching vertices by planar position and regions
+     * by site.
+     *
+     * Regions are considered moved if their planar center changed. A vertex which moved has no
+     * match at its old or new position, so unmatched vertices of each terrain are paired off as
+     * moved and the rest are added or removed. Changes in height alone are reported through the
+     * max height delta.
+     */
+    pub fn diff(&self, other: &VoronoiTerrain) -> TerrainDiff {
+        let planar_key = |x: f64, y: f64| (x.to_bits(), y.to_bits());
+
+        let other_heights: HashMap<(u64, u64), f64> = other.terrain_graph.vertices
+            .iter()
+            .map(|vertex| (planar_key(vertex.position.x, vertex.position.y), vertex.position.z))
+            .collect();
+
+        let mut matched_vertices = 0;
+        let mut max_height_delta: f64 = 0.0;
+        for vertex in self.terrain_graph.vertices.iter() {
+            if let Some(height) = other_heights.get(&planar_key(vertex.position.x, vertex.position.y)) {
+                matched_vertices += 1;
+                max_height_delta = max_height_delta.max((vertex.position.z - height).abs());
             }
         }
 
-        // Create terrain vertices.
-        let mut terrain_vertices: Vec<TerrainVertex> = Vec::with_capacity(dt_vertices.len());
-        for (i, vertex) in dt_vertices.into_iter().enumerate() {
-            let height = noise.get([vertex.0, vertex.1]);
-            let terrain_vertex = TerrainVertex {
-            position: Point3::new(vertex.0, vertex.1, height),
-                normal: Vector3::zero(),
-                edges: terrain_edges_by_vertex_index.remove(&i).unwrap(),
-            };
-            terrain_vertices.push(terrain_vertex);
+        let unmatched_vertices = self.terrain_graph.vertices.len() - matched_vertices;
+        let other_unmatched_vertices = other.terrain_graph.vertices.len() - matched_vertices;
+        let moved_vertices = unmatched_vertices.min(other_unmatched_vertices);
+
+        let other_centers: HashMap<(u64, u64), Point3<f64>> = other.site_of_region
+            .iter()
+            .zip(other.region_graph.vertices.iter())
+            .map(|(site, region)| (planar_key(site.0, site.1), region.center))
+            .collect();
+
+        let mut matched_regions = 0;
+        let mut moved_regions = 0;
+        for (site, region) in self.site_of_region.iter().zip(self.region_graph.vertices.iter()) {
+            if let Some(center) = other_centers.get(&planar_key(site.0, site.1)) {
+                matched_regions += 1;
+                if center.x != region.center.x || center.y != region.center.y {
+                    moved_regions += 1;
+                }
+            }
+        }
+
+        TerrainDiff {
+            added_vertices: other_unmatched_vertices - moved_vertices,
+            removed_vertices: unmatched_vertices - moved_vertices,
+            moved_vertices: moved_vertices,
+            added_regions: other.region_graph.vertices.len() - matched_regions,
+            removed_regions: self.region_graph.vertices.len() - matched_regions,
+            moved_regions: moved_regions,
+            max_height_delta: max_height_delta,
         }
+    }
 
-        // Create regions.
-        let mut regions: Vec<Region> = Vec::with_capacity(dt_cells.len());
+    /** The height of the water plane, the water level mapped from [0, height] into `NOISE_RANGE`. */
+    pub fn water_height(&self) -> f64 {
+        water_height(self.water_level, self.height)
+    }
 
-        for dt_cell in dt_cells.iter() {
+    /**
+     * Calculates the fraction of the land area which is traversable, e.g. for map statistics.
+     *
+     * A land region is walkable if the steepest edge of its boundary is less steep than
+     * `max_slope`. Areas are clipped to the bounds of the sites, see `region_areas`, so the far
+     * reaching outer regions don't dominate. Without land the fraction is zero.
+     */
+    pub fn walkable_fraction(&self, max_slope: f64) -> f64 {
+        let vertices = &self.terrain_graph.vertices;
+        let water_height = self.water_height();
 
-            // Calculate the normal from 3 verices.
-            let p0 = terrain_vertices[dt_cell[0]].position;
-            let e1 = terrain_vertices[dt_cell[1]].position - p0;
-            let e2 = terrain_vertices[dt_cell[2]].position - p0;
-            let normal = e1.cross(e2);
-            normal.normalize();
+        let mut land_area = 0.0;
+        let mut walkable_area = 0.0;
+        for (region, area) in self.region_graph.vertices.iter().zip(self.region_areas()) {
+            if region.center.z < water_height {
+                continue;
+            }
+            land_area += area;
 
-            let region = Region {
-                center: Point3::new(0.0, 0.0, 0.0),
-                normal: normal,
-                edges: Vec::new(), // TODO(orglofch):
-            };
-            regions.push(region);
+            let polygon = &region.vertices;
+            let steepest = (0..polygon.len())
+                .map(|i| edge_slope(vertices[polygon[i]].position, vertices[polygon[(i + 1) % polygon.len()]].position))
+                .fold(0.0, f64::max);
+            if steepest < max_slope {
+                walkable_area += area;
+            }
+        }
+
+        if land_area == 0.0 { 0.0 } else { walkable_area / land_area }
+    }
+
+    /** Scores the fertility of each region with the default weights, see `fertility_with`. */
+    pub fn fertility(&self) -> Vec<f64> {
+        self.fertility_with(&FertilityParams::default())
+    }
+
+    /**
+     * Scores the fertility of each land region from its moisture, temperature and flatness.
+     *
+     * Each factor lies in [0, 1]:
+     *  - Moisture decays exponentially with the travel distance to the nearest water region.
+     *  - Temperature falls linearly from 1 at the water level to 0 at the maximum height.
+     *  - Flatness is the vertical component of the region normal.
+     *
+     * @return The weighted average of the factors for each region, zero for underwater regions.
+     */
+    pub fn fertility_with(&self, params: &FertilityParams) -> Vec<f64> {
+        let regions = &self.region_graph.vertices;
+        let water_height = self.water_height();
+
+        let sources: Vec<(usize, f64)> = (0..regions.len())
+            .filter(|&region| regions[region].center.z < water_height)
+            .map(|region| (region, 0.0))
+            .collect();
+        let (distances, _) = dijkstra(&sources,
+                                      self.region_adjacency(),
+                                      |a, b| self.region_distance(a, b));
+
+        let total_weight = params.moisture_weight + params.temperature_weight + params.flatness_weight;
+        regions.iter()
+            .enumerate()
+            .map(|(region_index, region)| {
+                if region.center.z < water_height || total_weight <= 0.0 {
+                    return 0.0;
+                }
+                let moisture = if params.moisture_range > 0.0 {
+                    (-distances[region_index] / params.moisture_range).exp()
+                } else {
+                    0.0
+                };
+                let temperature = 1.0 - ((region.center.z - water_height) / (NOISE_RANGE.1 - water_height))
+                    .clamp(0.0, 1.0);
+                let flatness = region.normal.z.clamp(0.0, 1.0);
+
+                (params.moisture_weight * moisture + params.temperature_weight * temperature +
+                 params.flatness_weight * flatness) / total_weight
+            })
+            .collect()
+    }
+
+    /**
+     * Estimates the volume of water held by the terrain at the current water level.
+     *
+     * Each underwater region contributes its area, see `region_areas`, times the depth of its
+     * center.
+     */
+    pub fn water_volume(&self) -> f64 {
+        let water_height = self.water_height();
+        self.region_graph.vertices.iter()
+            .zip(self.region_areas())
+            .filter(|&(region, _)| region.center.z < water_height)
+            .map(|(region, area)| (water_height - region.center.z) * area)
+            .sum()
+    }
+
+    /**
+     * Calculates the shortest travel distance between every pair of regions.
+     *
+     * Regions are connected by the 3D distance between neighbouring region centers. Dijkstra is run
+     * from every region so this takes O(n^2 log n) time and O(n^2) memory, prefer single source
+     * queries for large terrains.
+     *
+     * @return The distance between each pair of regions, infinite if unreachable.
+     */
+    pub fn region_distance_matrix(&self) -> Vec<Vec<f64>> {
+        let adjacency = self.region_adjacency();
+
+        (0..adjacency.len())
+            .map(|region| {
+                let (distances, _) = dijkstra(&[(region, 0.0)], adjacency, |a, b| self.region_distance(a, b));
+                distances
+            })
+            .collect()
+    }
+
+    /**
+     * Finds the shortest path between two regions which never crosses water.
+     *
+     * Regions whose centers lie below the water level are excluded entirely, including as
+     * endpoints. Regions only sharing a border outside of the bounds of the sites aren't
+     * considered neighbours, so the path can't skirt around water beyond the terrain.
+     *
+     * @return The regions along the path from `from` to `to` inclusive, `None` if no all-land
+     *         path exists.
+     */
+    pub fn land_path(&self, from: usize, to: usize) -> Option<Vec<usize>> {
+        let regions = &self.region_graph.vertices;
+        let water_height = self.water_height();
+        let is_land = |region: usize| regions[region].center.z >= water_height;
+        if !is_land(from) || !is_land(to) {
+            return None;
+        }
+
+        let (distances, previous) = dijkstra(&[(from, 0.0)], &self.land_adjacency(), |a, b| {
+            self.region_distance(a, b)
+        });
+        if distances[to].is_infinite() {
+            return None;
         }
+        Some(trace_path(&previous, to))
+    }
 
-        let terrain_graph = Graph {
-            vertices: terrain_vertices,
-            edges: terrain_edges,
+    /**
+     * Connects hub regions, e.g. capitals, with a network of roads over land.
+     *
+     * Roads follow the cheapest all-land paths, where the cost of each step is its length scaled
+     * up by its slope. The hubs are joined along a minimum spanning tree of these path costs, hubs
+     * which can't be reached over land are left unconnected.
+     *
+     * @return The regions along each road, from the hub already in the network to the new hub.
+     */
+    pub fn road_network(&self, hubs: &[usize]) -> Vec<Vec<usize>> {
+        let regions = &self.region_graph.vertices;
+        let adjacency = self.land_adjacency();
+        let cost = |a: usize, b: usize| {
+            self.region_distance(a, b) * (1.0 + edge_slope(regions[a].center, regions[b].center))
         };
 
-        let region_graph = Graph {
-            vertices: regions,
-            edges: region_edges,
+        let paths: Vec<(Vec<f64>, Vec<Option<usize>>)> = hubs.iter()
+            .map(|&hub| dijkstra(&[(hub, 0.0)], &adjacency, cost))
+            .collect();
+
+        // Prim's algorithm over the hubs, with the path costs between them as edge weights.
+        let mut connected: Vec<bool> = vec![false; hubs.len()];
+        let mut roads: Vec<Vec<usize>> = Vec::new();
+        for start in 0..hubs.len() {
+            if connected[start] {
+                continue;
+            }
+            connected[start] = true;
+
+            loop {
+                let mut cheapest: Option<(usize, usize, f64)> = None;
+                for from in (0..hubs.len()).filter(|&from| connected[from]) {
+                    for to in (0..hubs.len()).filter(|&to| !connected[to]) {
+                        let road_cost = paths[from].0[hubs[to]];
+                        if road_cost.is_finite() && cheapest.is_none_or(|(_, _, cheapest)| road_cost < cheapest) {
+                            cheapest = Some((from, to, road_cost));
+                        }
+                    }
+                }
+
+                match cheapest {
+                    Some((from, to, _)) => {
+                        connected[to] = true;
+                        roads.push(trace_path(&paths[from].1, hubs[to]));
+                    },
+                    None => break,
+                }
+            }
+        }
+        roads
+    }
+
+    /** The local region adjacency restricted to land, underwater regions have no neighbours. */
+    fn land_adjacency(&self) -> Vec<Vec<usize>> {
+        let regions = &self.region_graph.vertices;
+        let water_height = self.water_height();
+        let is_land = |region: usize| regions[region].center.z >= water_height;
+
+        self.local_adjacency()
+            .into_iter()
+            .enumerate()
+            .map(|(region, neighbours)| {
+                if !is_land(region) {
+                    return Vec::new();
+                }
+                neighbours.into_iter().filter(|&neighbour| is_land(neighbour)).collect()
+            })
+            .collect()
+    }
+
+    /**
+     * Finds land bridges, the land regions whose removal would split a landmass in two.
+     *
+     * These are the articulation points of the region graph restricted to land, found with an
+     * iterative depth first search tracking the lowest discovery time reachable from each subtree.
+     *
+     * @return The isthmus regions in ascending order.
+     */
+    pub fn isthmuses(&self) -> Vec<usize> {
+        let regions = &self.region_graph.vertices;
+        let water_height = self.water_height();
+        let is_land = |region: usize| regions[region].center.z >= water_height;
+        let adjacency = self.local_adjacency();
+
+        let mut discovery: Vec<Option<usize>> = vec![None; regions.len()];
+        let mut low: Vec<usize> = vec![0; regions.len()];
+        let mut articulation: Vec<bool> = vec![false; regions.len()];
+        let mut time = 0;
+
+        for root in (0..regions.len()).filter(|&region| is_land(region)) {
+            if discovery[root].is_some() {
+                continue;
+            }
+            discovery[root] = Some(time);
+            low[root] = time;
+            time += 1;
+
+            let mut root_children = 0;
+            // The region, its parent and the index of the next neighbour to visit.
+            let mut stack: Vec<(usize, Option<usize>, usize)> = vec![(root, None, 0)];
+            while let Some(&mut (region, parent, ref mut next)) = stack.last_mut() {
+                if *next < adjacency[region].len() {
+                    let neighbour = adjacency[region][*next];
+                    *next += 1;
+                    if !is_land(neighbour) || Some(neighbour) == parent {
+                        continue;
+                    }
+                    match discovery[neighbour] {
+                        Some(neighbour_discovery) => low[region] = low[region].min(neighbour_discovery),
+                        None => {
+                            discovery[neighbour] = Some(time);
+                            low[neighbour] = time;
+                            time += 1;
+                            stack.push((neighbour, Some(region), 0));
+                        },
+                    }
+                    continue;
+                }
+
+                stack.pop();
+                match parent {
+                    Some(parent) if parent == root => root_children += 1,
+                    Some(parent) if low[region] >= discovery[parent].unwrap() => articulation[parent] = true,
+                    _ => (),
+                }
+                if let Some(parent) = parent {
+                    low[parent] = low[parent].min(low[region]);
+                }
+            }
+
+            // The root is only an articulation point if it has several independent subtrees.
+            if root_children > 1 {
+                articulation[root] = true;
+            }
+        }
+
+        (0..regions.len()).filter(|&region| articulation[region]).collect()
+    }
+
+    /**
+     * Finds the regions reachable from a region within a travel budget, e.g. for movement ranges.
+     *
+     * Runs Dijkstra over the region graph, stopping once the cheapest unvisited region exceeds
+     * the budget.
+     *
+     * @param cost The cost of entering each region.
+     * @return The regions whose cheapest path costs at most `budget`, including `start`, in
+     *         ascending order.
+     */
+    pub fn isochrone<F>(&self, start: usize, budget: f64, cost: F) -> Vec<usize>
+        where F: Fn(usize) -> f64
+    {
+        let adjacency = self.region_adjacency();
+        let mut costs = vec![f64::INFINITY; adjacency.len()];
+        let mut reached: Vec<usize> = Vec::new();
+        let mut heap = BinaryHeap::new();
+
+        costs[start] = 0.0;
+        heap.push(MinCost(0.0, start));
+        while let Some(MinCost(region_cost, region)) = heap.pop() {
+            if region_cost > budget {
+                break;
+            }
+            // Skip stale entries which have since been reached more cheaply.
+            if region_cost > costs[region] {
+                continue;
+            }
+            reached.push(region);
+
+            for &neighbour in adjacency[region].iter() {
+                let neighbour_cost = region_cost + cost(neighbour);
+                if neighbour_cost < costs[neighbour] {
+                    costs[neighbour] = neighbour_cost;
+                    heap.push(MinCost(neighbour_cost, neighbour));
+                }
+            }
+        }
+
+        reached.sort();
+        reached
+    }
+
+    /**
+     * Ranks coastal land regions as natural harbors, e.g. for placing ports.
+     *
+     * A coastal region is scored by how enclosed its neighbouring water is, the average fraction
+     * of each water neighbour's own neighbours which are land. Water in a bay scores highly
+     * while open sea along a straight coast scores low. Only neighbours sharing a border within
+     * the bounds of the sites are counted, as hull regions also border distant hull regions.
+     *
+     * @return Coastal land regions from most to least sheltered.
+     */
+    pub fn harbor_candidates(&self) -> Vec<usize> {
+        let regions = &self.region_graph.vertices;
+        let adjacency = self.local_adjacency();
+        let water_height = self.water_height();
+        let is_land = |region: usize| regions[region].center.z >= water_height;
+
+        let enclosure = |region: usize| {
+            let neighbours = &adjacency[region];
+            if neighbours.is_empty() {
+                return 0.0;
+            }
+            neighbours.iter().filter(|&&neighbour| is_land(neighbour)).count() as f64 / neighbours.len() as f64
         };
 
-        VoronoiTerrain {
-            terrain_graph: terrain_graph,
-            region_graph: region_graph,
-            water_level: self.water_level,
+        let mut candidates: Vec<(usize, f64)> = (0..regions.len())
+            .filter(|&region| is_land(region))
+            .filter_map(|region| {
+                let water: Vec<usize> = adjacency[region].iter()
+                    .cloned()
+                    .filter(|&neighbour| !is_land(neighbour))
+                    .collect();
+                if water.is_empty() {
+                    return None;
+                }
+                let score = water.iter().map(|&neighbour| enclosure(neighbour)).sum::<f64>() / water.len() as f64;
+                Some((region, score))
+            })
+            .collect();
+
+        // Ties are broken by region index to keep the ordering deterministic.
+        candidates.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap().then(a.0.cmp(&b.0)));
+        candidates.into_iter().map(|(region, _)| region).collect()
+    }
+
+    /** The 3D distance between the centers of two regions. */
+    pub fn region_distance(&self, a: usize, b: usize) -> f64 {
+        let regions = &self.region_graph.vertices;
+        (regions[b].center - regions[a].center).magnitude()
+    }
+
+    /**
+     * Selects regions which are maximally spread out, e.g. for placing capitals.
+     *
+     * Uses farthest point sampling over the sites of the regions, starting from the region
+     * furthest from the centroid of all sites. Sites are used rather than region centers as the
+     * centers of the outer regions are dragged far out by their unbounded boundaries.
+     *
+     * @return Up to `k` region indices in the order they were selected.
+     */
+    pub fn select_spread_regions(&self, k: usize) -> Vec<usize> {
+        let sites = &self.site_of_region;
+        if sites.is_empty() || k == 0 {
+            return Vec::new();
+        }
+
+        let sum = sites.iter().fold((0.0, 0.0), |sum, site| (sum.0 + site.0, sum.1 + site.1));
+        let centroid = (sum.0 / sites.len() as f64, sum.1 / sites.len() as f64);
+
+        // The squared distance from each region to its nearest selected region.
+        let mut nearest: Vec<f64> = sites.iter().map(|&site| distance2(site, centroid)).collect();
+
+        let mut selected: Vec<usize> = Vec::with_capacity(k);
+        while selected.len() < k.min(sites.len()) {
+            let next = (0..sites.len())
+                .fold(0, |best, region| if nearest[region] > nearest[best] { region } else { best });
+            selected.push(next);
+
+            for region in 0..sites.len() {
+                nearest[region] = nearest[region].min(distance2(sites[region], sites[next]));
+            }
         }
+        selected
     }
-}
 
-pub struct TerrainVertex {
-    pub position: Point3<f64>,
-    pub normal: Vector3<f64>,
+    /**
+     * Smooths the heights across a vertical seam, e.g. where two terrains were stitched together.
+     *
+     * Each terrain vertex within `width` of the seam is pulled toward the average of the mean
+     * heights on either side of the seam nearby, those within `width` of the vertex. The pull is
+     * strongest on the seam and fades out linearly to nothing at `width`.
+     */
+    pub fn blend_seam(&mut self, seam_x: f64, width: f64) {
+        if width <= 0.0 {
+            return;
+        }
 
-    // Indices into the set of terrain_edges.
-    pub edges: Vec<usize>,
-}
+        let band: Vec<usize> = (0..self.terrain_graph.vertices.len())
+            .filter(|&i| (self.terrain_graph.vertices[i].position.x - seam_x).abs() < width)
+            .collect();
+        let positions: Vec<Point3<f64>> = band.iter().map(|&i| self.terrain_graph.vertices[i].position).collect();
 
-pub struct Region {
-    pub center: Point3<f64>,
-    pub normal: Vector3<f64>,
+        let heights: Vec<f64> = positions.iter()
+            .map(|&position| {
+                // The sum and count of the nearby heights left and right of the seam.
+                let mut sides = [(0.0, 0), (0.0, 0)];
+                for other in positions.iter() {
+                    if distance2((position.x, position.y), (other.x, other.y)) <= width * width {
+                        let side = &mut sides[if other.x < seam_x { 0 } else { 1 }];
+                        side.0 += other.z;
+                        side.1 += 1;
+                    }
+                }
+                let means: Vec<f64> = sides.iter()
+                    .filter(|&&(_, count)| count > 0)
+                    .map(|&(sum, count)| sum / count as f64)
+                    .collect();
+                let target = means.iter().sum::<f64>() / means.len() as f64;
 
-    // Indices into the set of region_edges.
-    pub edges: Vec<usize>,
-}
+                let weight = 1.0 - (position.x - seam_x).abs() / width;
+                position.z + (target - position.z) * weight
+            })
+            .collect();
 
-pub struct Graph<T> {
-    pub vertices: Vec<T>,
-    pub edges: Vec<(usize, usize)>,
+        for (&i, &z) in band.iter().zip(heights.iter()) {
+            self.terrain_graph.vertices[i].position.z = z;
+        }
+        update_geometry(&mut self.terrain_graph, &mut self.region_graph);
+    }
+
+    /**
+     * Sets the height of a single terrain vertex, updating only the geometry it affects.
+     *
+     * The centers and normals of the regions bounded by the vertex are recalculated, as are the
+     * normals of those regions' vertices. Region areas are planar so are unaffected.
+     *
+     * @return The indices of the regions which changed.
+     */
+    pub fn set_vertex_height(&mut self, v: usize, z: f64) -> Vec<usize> {
+        self.terrain_graph.vertices[v].position.z = z;
+
+        let changed: Vec<usize> = self.regions_by_vertex()[v].to_vec();
+
+        // Heights don't change which regions a vertex bounds, so the cache stays valid.
+        let regions_by_vertex = self.regions_by_vertex.get().unwrap();
+        let vertices = &mut self.terrain_graph.vertices;
+        let regions = &mut self.region_graph.vertices;
+
+        for &region_index in changed.iter() {
+            let region = &mut regions[region_index];
+            region.center = region_center(vertices, &region.vertices);
+            region.normal = region_normal(vertices, &region.vertices);
+        }
+
+        // Vertex normals average the normals of all of their regions, not just the changed ones.
+        let mut affected: Vec<usize> = changed.iter()
+            .flat_map(|&region_index| regions[region_index].vertices.iter().cloned())
+            .collect();
+        affected.sort();
+        affected.dedup();
+
+        for &vertex_index in affected.iter() {
+            let normal = regions_by_vertex[vertex_index].iter()
+                .fold(Vector3::zero(), |sum, &region_index| sum + regions[region_index].normal);
+            vertices[vertex_index].normal = if normal != Vector3::zero() { normal.normalize() } else { normal };
+        }
+
+        changed
+    }
+
+    /**
+     * Calculates the diameter of the region graph, the longest shortest path in hops.
+     *
+     * This is exact, running a BFS from every region in O(n * (n + e)) time. A double BFS from
+     * the furthest region is O(n + e) but only exact for trees, so isn't used. Unreachable pairs
+     * of regions are ignored.
+     */
+    pub fn region_graph_diameter(&self) -> usize {
+        let adjacency = self.region_adjacency();
+
+        (0..adjacency.len())
+            .map(|start| {
+                let mut hops: Vec<Option<usize>> = vec![None; adjacency.len()];
+                let mut queue: VecDeque<usize> = VecDeque::new();
+                hops[start] = Some(0);
+                queue.push_back(start);
+
+                let mut furthest = 0;
+                while let Some(region) = queue.pop_front() {
+                    let region_hops = hops[region].unwrap();
+                    furthest = furthest.max(region_hops);
+                    for &neighbour in adjacency[region].iter() {
+                        if hops[neighbour].is_none() {
+                            hops[neighbour] = Some(region_hops + 1);
+                            queue.push_back(neighbour);
+                        }
+                    }
+                }
+                furthest
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /**
+     * Calculates the absolute difference between the center heights of the regions on either
+     * side of each region edge, highlighting escarpments.
+     *
+     * @return The height delta of each region edge, indexed by region edge.
+     */
+    pub fn region_edge_height_deltas(&self) -> Vec<f64> {
+        let regions = &self.region_graph.vertices;
+        self.region_graph.edges
+            .iter()
+            .map(|&(a, b)| (regions[a].center.z - regions[b].center.z).abs())
+            .collect()
+    }
+
+    /**
+     * Finds the region edges which are cliffs, e.g. for rendering vertical cliff faces.
+     *
+     * @return The indices of the region edges whose height delta exceeds `min_delta`.
+     */
+    pub fn cliff_edges(&self, min_delta: f64) -> Vec<usize> {
+        self.region_edge_height_deltas()
+            .into_iter()
+            .enumerate()
+            .filter(|&(_, delta)| delta > min_delta)
+            .map(|(edge_index, _)| edge_index)
+            .collect()
+    }
+
+    /**
+     * Finds the steepest region, whose normal deviates most from vertical.
+     *
+     * @return The region index or `None` if there are no regions.
+     */
+    pub fn steepest_region(&self) -> Option<usize> {
+        // Normals are upward facing unit vectors so the smallest z is the furthest from vertical.
+        self.region_graph.vertices.iter()
+            .enumerate()
+            .fold(None, |steepest: Option<(usize, f64)>, (region_index, region)| match steepest {
+                Some((_, z)) if z <= region.normal.z => steepest,
+                _ => Some((region_index, region.normal.z)),
+            })
+            .map(|(region_index, _)| region_index)
+    }
+
+    /**
+     * Determines which regions are snow capped.
+     *
+     * North is +y with the sun to the south, so the snow line is raised on sun facing (southern)
+     * slopes and lowered on shaded (northern) slopes, in proportion to their steepness. The snow
+     * line is also lowered toward the north of the terrain.
+     *
+     * @param base_snow_line The snow line height for flat ground at the middle of the terrain.
+     * @return Whether each region is snowy, indexed by region.
+     */
+    pub fn snow_mask(&self, base_snow_line: f64) -> Vec<bool> {
+        // The largest shifts of the snow line due to aspect and latitude.
+        const ASPECT_SHIFT: f64 = 0.2;
+        const LATITUDE_SHIFT: f64 = 0.2;
+
+        let (min, max) = self.site_bounds();
+        let latitude = |y: f64| if max.1 > min.1 { (y - min.1) / (max.1 - min.1) - 0.5 } else { 0.0 };
+
+        self.region_graph.vertices.iter()
+            .map(|region| {
+                let snow_line = base_snow_line - ASPECT_SHIFT * region.normal.y -
+                                LATITUDE_SHIFT * latitude(region.center.y);
+                region.center.z > snow_line
+            })
+            .collect()
+    }
+
+    /**
+     * Morphs the terrain between the noise fields of two seeds, e.g. to animate evolving terrain.
+     *
+     * Only the normalized noise is interpolated, builder shaping such as concentric elevation
+     * isn't reapplied. Region centers and all normals are recalculated.
+     *
+     * @param t The interpolation factor, 0 for `seed_a`'s heights and 1 for `seed_b`'s.
+     */
+    pub fn morph_heights(&mut self, seed_a: u64, seed_b: u64, t: f64) {
+        let noise_a = Fbm::new().set_seed(seed_a as usize);
+        let noise_b = Fbm::new().set_seed(seed_b as usize);
+
+        for vertex in self.terrain_graph.vertices.iter_mut() {
+            let point = [vertex.position.x, vertex.position.y];
+            let height_a = normalize_fbm(noise_a.get(point));
+            let height_b = normalize_fbm(noise_b.get(point));
+            vertex.position.z = height_a + (height_b - height_a) * t;
+        }
+
+        update_geometry(&mut self.terrain_graph, &mut self.region_graph);
+    }
+
+    /**
+     * Generates a pseudo-random place name for each region from tables of syllables.
+     *
+     * Each region's name depends only on the seed and its index, so the same seed always yields
+     * the same names.
+     */
+    pub fn region_names(&self, seed: u64) -> Vec<String> {
+        let onsets = ["b", "br", "d", "dr", "f", "g", "gr", "h", "k", "l", "m", "n", "r", "s", "st", "t",
+                      "th", "v", "w", "z"];
+        let vowels = ["a", "e", "i", "o", "u", "ae", "ei", "ou"];
+        let codas = ["", "", "n", "r", "l", "s", "th", "nd", "rk", "m"];
+
+        (0..self.region_graph.vertices.len())
+            .map(|region| {
+                // The constant word keeps the seed from being all zeros, which XorShift rejects.
+                let mut rng = XorShiftRng::from_seed([seed as u32, (seed >> 32) as u32, region as u32, 0x9e37_79b9]);
+
+                let syllables = rng.gen_range(2, 4);
+                let mut name = String::new();
+                for _ in 0..syllables {
+                    name.push_str(onsets[rng.gen_range(0, onsets.len())]);
+                    name.push_str(vowels[rng.gen_range(0, vowels.len())]);
+                }
+                name.push_str(codas[rng.gen_range(0, codas.len())]);
+
+                let mut chars = name.chars();
+                match chars.next() {
+                    Some(first) => first.to_uppercase().chain(chars).collect(),
+                    None => name,
+                }
+            })
+            .collect()
+    }
+
+    /**
+     * Determines which regions are shadowed from a directional light.
+     *
+     * A region is shadowed if a ray from its center toward the light passes below the center of
+     * a region along the way, found by marching across the region graph toward the light.
+     *
+     * @param light_dir The direction toward the light.
+     * @return Whether each region is shadowed, indexed by region.
+     */
+    pub fn cast_shadows(&self, light_dir: Vector3<f64>) -> Vec<bool> {
+        let regions = &self.region_graph.vertices;
+
+        // Lights below the horizon shadow everything, lights directly overhead shadow nothing.
+        if light_dir.z <= 0.0 {
+            return vec![true; regions.len()];
+        }
+        let horizontal = (light_dir.x * light_dir.x + light_dir.y * light_dir.y).sqrt();
+        if horizontal == 0.0 {
+            return vec![false; regions.len()];
+        }
+
+        let direction = (light_dir.x / horizontal, light_dir.y / horizontal);
+        let rise = light_dir.z / horizontal;
+        let adjacency = self.region_adjacency();
+
+        (0..regions.len())
+            .map(|region| {
+                let start = regions[region].center;
+                self.march_regions(adjacency, region, direction)
+                    .into_iter()
+                    .any(|other| {
+                        let center = regions[other].center;
+                        let distance = distance2((center.x, center.y), (start.x, start.y)).sqrt();
+                        center.z > start.z + distance * rise
+                    })
+            })
+            .collect()
+    }
+
+    /**
+     * Calculates the moisture reaching each region on a prevailing wind, casting rain shadows.
+     *
+     * Air is traced upwind from each region by marching across the region graph. It starts
+     * saturated at the far end or whenever it crosses water, and rains out moisture as it's forced
+     * up over the terrain, so the lee side of mountains is left dry.
+     *
+     * @param wind The planar direction the wind blows toward.
+     * @return The moisture of each region, from 0 for bone dry to 1 for saturated.
+     */
+    pub fn rain_shadow(&self, wind: Vector2<f64>) -> Vec<f64> {
+        let regions = &self.region_graph.vertices;
+        let length = (wind.x * wind.x + wind.y * wind.y).sqrt();
+        if length == 0.0 {
+            return vec![1.0; regions.len()];
+        }
+
+        // The fraction of moisture kept per unit of rise is e^-depletion.
+        let depletion = 4.0;
+        let water_height = self.water_height();
+        let upwind = (-wind.x / length, -wind.y / length);
+        let adjacency = self.region_adjacency();
+
+        (0..regions.len())
+            .map(|region| {
+                let mut path = self.march_regions(adjacency, region, upwind);
+                path.reverse();
+                path.push(region);
+
+                let mut moisture = 1.0;
+                for step in path.windows(2) {
+                    let (from, to) = (regions[step[0]].center.z, regions[step[1]].center.z);
+                    if to < water_height {
+                        moisture = 1.0;
+                    } else if to > from {
+                        moisture *= (-depletion * (to - from)).exp();
+                    }
+                }
+                moisture
+            })
+            .collect()
+    }
+
+    /**
+     * Scores how exposed each region is to a prevailing wind, e.g. for placing windmills.
+     *
+     * As with `rain_shadow` the terrain upwind of each region is found by marching across the
+     * region graph. Regions with taller terrain upwind are sheltered and score zero, others score
+     * their height above the water level, in [0, 1], scaled down as their slope faces away from
+     * the wind.
+     *
+     * @param wind The planar direction the wind blows toward.
+     * @return The exposure of each region, from 0 for sheltered to 1 for fully exposed.
+     */
+    pub fn wind_exposure(&self, wind: Vector2<f64>) -> Vec<f64> {
+        let regions = &self.region_graph.vertices;
+        let length = (wind.x * wind.x + wind.y * wind.y).sqrt();
+        if length == 0.0 {
+            return vec![0.0; regions.len()];
+        }
+
+        let water_height = self.water_height();
+        let upwind = (-wind.x / length, -wind.y / length);
+        let adjacency = self.region_adjacency();
+
+        (0..regions.len())
+            .map(|region| {
+                let center = regions[region].center;
+                let sheltered = self.march_regions(adjacency, region, upwind)
+                    .into_iter()
+                    .any(|other| regions[other].center.z > center.z);
+                if sheltered {
+                    return 0.0;
+                }
+
+                let elevation = ((center.z - water_height) / (NOISE_RANGE.1 - water_height)).clamp(0.0, 1.0);
+
+                // Flat regions score half, slopes score more as they turn to face into the wind.
+                let normal = regions[region].normal;
+                let facing = 0.5 + 0.5 * (normal.x * upwind.0 + normal.y * upwind.1);
+                elevation * facing
+            })
+            .collect()
+    }
+
+    /**
+     * Walks across the region graph from a region in a planar direction.
+     *
+     * Each step moves to the neighbour whose center lies furthest along the direction, the walk
+     * ends when no neighbour lies further along it.
+     *
+     * @return The regions visited after the starting region, in order.
+     */
+    fn march_regions(&self, adjacency: &[Vec<usize>], start: usize, direction: (f64, f64)) -> Vec<usize> {
+        let regions = &self.region_graph.vertices;
+        let mut path: Vec<usize> = Vec::new();
+
+        let mut current = start;
+        loop {
+            let center = regions[current].center;
+            let next = adjacency[current].iter()
+                .map(|&neighbour| {
+                    let offset = regions[neighbour].center - center;
+                    (neighbour, offset.x * direction.0 + offset.y * direction.1)
+                })
+                .filter(|&(_, progress)| progress > 0.0)
+                .fold(None, |best: Option<(usize, f64)>, (neighbour, progress)| match best {
+                    Some((_, best_progress)) if best_progress >= progress => best,
+                    _ => Some((neighbour, progress)),
+                });
+
+            match next {
+                Some((neighbour, _)) => {
+                    path.push(neighbour);
+                    current = neighbour;
+                },
+                None => return path,
+            }
+        }
+    }
+
+    /**
+     * Finds the region containing a position.
+     *
+     * @return The region index or `None` if the position lies outside of the terrain.
+     */
+    pub fn region_of(&self, x: f64, y: f64) -> Option<usize> {
+        // The voronoi cell containing the position is the one with the nearest site.
+        self.nearest_region(x, y)
+            .filter(|&region| {
+                let polygon = self.region_polygon(region);
+
+                // The crossing test misses positions on the boundary, such as terrain vertices.
+                point_in_polygon((x, y), &polygon) ||
+                (0..polygon.len())
+                    .any(|i| segment_distance2((x, y), polygon[i], polygon[(i + 1) % polygon.len()]) < 1e-18)
+            })
+    }
+
+    /**
+     * Finds the land region nearest to a position, e.g. for spawning ships near the shore.
+     *
+     * Distance is measured to the boundary of each land region, or zero if the position lies
+     * within it.
+     *
+     * @return The nearest land region or `None` if there is no land.
+     */
+    pub fn nearest_land(&self, x: f64, y: f64) -> Option<usize> {
+        let water_height = self.water_height();
+        (0..self.region_graph.vertices.len())
+            .filter(|&region| self.region_graph.vertices[region].center.z >= water_height)
+            .map(|region| {
+                let polygon = self.region_polygon(region);
+                let distance2 = if point_in_polygon((x, y), &polygon) {
+                    0.0
+                } else {
+                    (0..polygon.len())
+                        .map(|i| segment_distance2((x, y), polygon[i], polygon[(i + 1) % polygon.len()]))
+                        .fold(f64::INFINITY, f64::min)
+                };
+                (region, distance2)
+            })
+            .fold(None, |nearest: Option<(usize, f64)>, (region, distance2)| match nearest {
+                Some((_, nearest_distance2)) if nearest_distance2 <= distance2 => nearest,
+                _ => Some((region, distance2)),
+            })
+            .map(|(region, _)| region)
+    }
+
+    /** Finds the region whose site is nearest to a position. */
+    fn nearest_region(&self, x: f64, y: f64) -> Option<usize> {
+        self.site_of_region.iter()
+            .map(|&site| distance2(site, (x, y)))
+            .enumerate()
+            .fold(None, |nearest: Option<(usize, f64)>, (region, distance2)| match nearest {
+                Some((_, nearest_distance2)) if nearest_distance2 <= distance2 => nearest,
+                _ => Some((region, distance2)),
+            })
+            .map(|(region, _)| region)
+    }
+
+    /**
+     * Interpolates the terrain height at a position.
+     *
+     * @return The height or the out of bounds height if the position lies outside of the terrain.
+     */
+    pub fn height_at(&self, x: f64, y: f64) -> Option<f64> {
+        match self.locate(x, y) {
+            Some((region_index, i, (wc, w0, w1))) => {
+                let region = &self.region_graph.vertices[region_index];
+                let vertices = &self.terrain_graph.vertices;
+                let v0 = region.vertices[i];
+                let v1 = region.vertices[(i + 1) % region.vertices.len()];
+                Some(wc * region.center.z + w0 * vertices[v0].position.z + w1 * vertices[v1].position.z)
+            },
+            None => self.out_of_bounds_height,
+        }
+    }
+
+    /**
+     * Samples the terrain height `step` away from a position in each cardinal direction.
+     *
+     * @return The heights to the north (+y), south (-y), east (+x) and west (-x).
+     */
+    pub fn neighbor_heights(&self, x: f64, y: f64, step: f64) -> [Option<f64>; 4] {
+        [self.height_at(x, y + step),
+         self.height_at(x, y - step),
+         self.height_at(x + step, y),
+         self.height_at(x - step, y)]
+    }
+
+    /**
+     * Calculates a terrain ruggedness index around a position.
+     *
+     * Averages the slopes, rise over planar run, of the boundary edges of regions whose centers
+     * lie within `radius`. Low values indicate flat, buildable land.
+     */
+    pub fn ruggedness(&self, x: f64, y: f64, radius: f64) -> f64 {
+        let vertices = &self.terrain_graph.vertices;
+
+        let mut edges: Vec<(usize, usize)> = self.region_graph.vertices.iter()
+            .filter(|region| distance2((region.center.x, region.center.y), (x, y)) <= radius * radius)
+            .flat_map(|region| {
+                let polygon = &region.vertices;
+                (0..polygon.len()).map(move |i| {
+                    let (a, b) = (polygon[i], polygon[(i + 1) % polygon.len()]);
+                    if a < b { (a, b) } else { (b, a) }
+                })
+            })
+            .collect();
+        edges.sort();
+        edges.dedup();
+
+        let slopes: Vec<f64> = edges.iter()
+            .map(|&(a, b)| edge_slope(vertices[a].position, vertices[b].position))
+            .collect();
+        if slopes.is_empty() {
+            return 0.0;
+        }
+        slopes.iter().sum::<f64>() / slopes.len() as f64
+    }
+
+    /**
+     * Traces the boundary of the land area as closed loops, e.g. for rendering the coastline.
+     *
+     * Boundary edges are the edges of land regions not shared with another land region, they are
+     * chained in the winding order of the regions. Each landmass yields an outer loop and each
+     * lake within it a hole, which winds in the opposite direction.
+     *
+     * @return The planar vertex positions of each loop, without repeating the first vertex.
+     */
+    pub fn land_outline(&self) -> Vec<Vec<(f64, f64)>> {
+        let vertices = &self.terrain_graph.vertices;
+        let water_height = self.water_height();
+
+        let mut land_edges: HashSet<(usize, usize)> = HashSet::new();
+        for region in self.region_graph.vertices.iter().filter(|region| region.center.z >= water_height) {
+            let polygon = &region.vertices;
+            for i in 0..polygon.len() {
+                land_edges.insert((polygon[i], polygon[(i + 1) % polygon.len()]));
+            }
+        }
+
+        // Outgoing boundary edges by vertex, a vertex pinched between landmasses has several.
+        let mut next: HashMap<usize, Vec<usize>> = HashMap::new();
+        let mut boundary: Vec<(usize, usize)> = land_edges.iter()
+            .cloned()
+            .filter(|&(a, b)| !land_edges.contains(&(b, a)))
+            .collect();
+        boundary.sort();
+        for &(a, b) in boundary.iter() {
+            next.entry(a).or_default().push(b);
+        }
+
+        let mut loops: Vec<Vec<(f64, f64)>> = Vec::new();
+        for &(start, _) in boundary.iter() {
+            let mut outline: Vec<(f64, f64)> = Vec::new();
+            let mut current = start;
+            while let Some(following) = next.get_mut(&current).and_then(|targets| targets.pop()) {
+                let position = vertices[current].position;
+                outline.push((position.x, position.y));
+                current = following;
+                if current == start {
+                    break;
+                }
+            }
+            if !outline.is_empty() {
+                loops.push(outline);
+            }
+        }
+        loops
+    }
+
+    /**
+     * Approximates the medial axis of the coastline, e.g. for fjord and river mouth analysis.
+     *
+     * The midpoints of the borders between land and water regions sample the coastline. The
+     * voronoi edges of these samples which lie entirely on land approximate the medial axis.
+     *
+     * @return Segments of the medial axis on the terrain surface.
+     */
+    pub fn coastline_medial_axis(&self) -> Vec<(Point3<f64>, Point3<f64>)> {
+        let water_height = self.water_height();
+        let regions = &self.region_graph.vertices;
+        let vertices = &self.terrain_graph.vertices;
+        let is_land = |region: usize| regions[region].center.z >= water_height;
+
+        let samples: Vec<(f64, f64)> = self.region_graph.edges
+            .iter()
+            .filter(|&&(a, b)| is_land(a) != is_land(b))
+            .filter_map(|&(a, b)| {
+                let shared: Vec<usize> = regions[a].vertices.iter()
+                    .cloned()
+                    .filter(|i| regions[b].vertices.contains(i))
+                    .collect();
+                if shared.len() != 2 {
+                    return None;
+                }
+                let (p0, p1) = (vertices[shared[0]].position, vertices[shared[1]].position);
+                Some(((p0.x + p1.x) * 0.5, (p0.y + p1.y) * 0.5))
+            })
+            .collect();
+        if samples.len() < 3 {
+            return Vec::new();
+        }
+
+        let mut dt = Delaunay2D::new((0.0, 0.0), 9999.0);
+        for sample in samples.iter() {
+            dt.add_point(*sample);
+        }
+        let (dt_vertices, dt_cells) = voronoi_regions(&dt);
+
+        let mut edges: Vec<(usize, usize)> = dt_cells.iter()
+            .flat_map(|cell| {
+                (0..cell.len()).map(move |i| {
+                    let (a, b) = (cell[i], cell[(i + 1) % cell.len()]);
+                    if a < b { (a, b) } else { (b, a) }
+                })
+            })
+            .filter(|&(a, b)| a != b)
+            .collect();
+        edges.sort();
+        edges.dedup();
+
+        let on_land = |i: usize| {
+            let (x, y) = dt_vertices[i];
+            self.height_at(x, y)
+                .filter(|&height| height >= water_height)
+                .map(|height| Point3::new(x, y, height))
+        };
+
+        edges.into_iter()
+            .filter_map(|(a, b)| match (on_land(a), on_land(b)) {
+                (Some(pa), Some(pb)) => Some((pa, pb)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /**
+     * Finds the regions visible from a viewpoint, e.g. for fog of war.
+     *
+     * A region is visible if its center is within range and the line of sight to it doesn't pass
+     * below the terrain.
+     *
+     * @param from The planar position of the viewer.
+     * @param eye_height The height of the viewer above the terrain.
+     * @param max_range The maximum planar distance which can be seen.
+     * @return The indices of the visible regions.
+     */
+    pub fn visible_regions(&self, from: (f64, f64), eye_height: f64, max_range: f64) -> Vec<usize> {
+        let eye = match self.height_at(from.0, from.1) {
+            Some(height) => Point3::new(from.0, from.1, height + eye_height),
+            None => return Vec::new(),
+        };
+
+        self.region_graph.vertices.iter()
+            .enumerate()
+            .filter(|&(_, region)| distance2((region.center.x, region.center.y), from) <= max_range * max_range)
+            .filter(|&(_, region)| self.line_of_sight(eye, region.center))
+            .map(|(region_index, _)| region_index)
+            .collect()
+    }
+
+    /**
+     * Calculates the outline of the ground visible from a region's center, e.g. for towers.
+     *
+     * Rays are cast in evenly spaced directions, each marching outward while tracking the
+     * steepest elevation angle seen so far. A ray ends at the last ground point visible before
+     * the first occluded one, the edge of the terrain, or `max_range`.
+     *
+     * @return The outline as a polygon, one point per ray.
+     */
+    pub fn visibility_polygon(&self, region: usize, max_range: f64) -> Vec<(f64, f64)> {
+        const RAYS: usize = 64;
+        const STEPS: usize = 64;
+        // The drop in slope which occludes, ignoring rounding in the interpolated heights.
+        const EPSILON: f64 = 1e-9;
+
+        let eye = self.region_graph.vertices[region].center;
+        let step = max_range / STEPS as f64;
+
+        (0..RAYS)
+            .map(|ray| {
+                let angle = ray as f64 / RAYS as f64 * 2.0 * std::f64::consts::PI;
+                let direction = (angle.cos(), angle.sin());
+
+                let mut visible_distance = 0.0;
+                let mut max_slope = f64::NEG_INFINITY;
+                for i in 1..STEPS + 1 {
+                    let distance = step * i as f64;
+                    let height = match self.height_at(eye.x + direction.0 * distance,
+                                                      eye.y + direction.1 * distance) {
+                        Some(height) => height,
+                        None => break,
+                    };
+
+                    let slope = (height - eye.z) / distance;
+                    if slope < max_slope - EPSILON {
+                        break;
+                    }
+                    max_slope = max_slope.max(slope);
+                    visible_distance = distance;
+                }
+
+                (eye.x + direction.0 * visible_distance, eye.y + direction.1 * visible_distance)
+            })
+            .collect()
+    }
+
+    /**
+     * Whether the segment between two points stays above the terrain, checked at a fixed number
+     * of samples along it. Samples outside of the terrain don't occlude.
+     */
+    fn line_of_sight(&self, from: Point3<f64>, to: Point3<f64>) -> bool {
+        const SAMPLES: usize = 64;
+
+        (1..SAMPLES).all(|i| {
+            let point = from + (to - from) * (i as f64 / SAMPLES as f64);
+            self.height_at(point.x, point.y).is_none_or(|height| height <= point.z)
+        })
+    }
+
+    /**
+     * Finds where a ray first hits the terrain surface, e.g. for click to edit.
+     *
+     * The ray is marched at a fixed number of steps across the bounds of the sites and the first
+     * crossing below the surface is refined by bisection.
+     *
+     * @return The hit region and point or `None` if the ray misses the terrain.
+     */
+    pub fn raycast(&self, ray: &Ray) -> Option<(usize, Point3<f64>)> {
+        const STEPS: usize = 256;
+        const BISECTIONS: usize = 32;
+
+        // Heights are positive above the surface, negative below and `None` outside the terrain.
+        let height_above = |t: f64| {
+            let point = ray.origin + ray.direction * t;
+            self.height_at(point.x, point.y).map(|height| point.z - height)
+        };
+        let hit = |t: f64| {
+            let point = ray.origin + ray.direction * t;
+            self.region_of(point.x, point.y).map(|region| (region, point))
+        };
+
+        // Vertical rays can only hit the surface directly below or above their origin.
+        if ray.direction.x == 0.0 && ray.direction.y == 0.0 {
+            let height = self.height_at(ray.origin.x, ray.origin.y)?;
+            let t = (height - ray.origin.z) / ray.direction.z;
+            return if t >= 0.0 { hit(t) } else { None };
+        }
+
+        // Clip the ray to the bounds of the sites.
+        let (min, max) = self.site_bounds();
+        let mut t_min: f64 = 0.0;
+        let mut t_max = f64::INFINITY;
+        for &(origin, direction, low, high) in [(ray.origin.x, ray.direction.x, min.0, max.0),
+                                                (ray.origin.y, ray.direction.y, min.1, max.1)].iter() {
+            if direction == 0.0 {
+                if origin < low || origin > high {
+                    return None;
+                }
+                continue;
+            }
+            let t0 = (low - origin) / direction;
+            let t1 = (high - origin) / direction;
+            t_min = t_min.max(t0.min(t1));
+            t_max = t_max.min(t0.max(t1));
+        }
+        if t_min > t_max {
+            return None;
+        }
+
+        let step = (t_max - t_min) / STEPS as f64;
+        let mut previous_t = t_min;
+        let mut previous_above = height_above(t_min);
+        for i in 1..STEPS + 1 {
+            let t = t_min + step * i as f64;
+            let above = height_above(t);
+
+            if let (Some(previous_height), Some(height)) = (previous_above, above) {
+                if previous_height >= 0.0 && height < 0.0 {
+                    let (mut low, mut high) = (previous_t, t);
+                    for _ in 0..BISECTIONS {
+                        let middle = (low + high) * 0.5;
+                        match height_above(middle) {
+                            Some(height) if height < 0.0 => high = middle,
+                            _ => low = middle,
+                        }
+                    }
+                    return hit(high);
+                }
+            }
+
+            previous_t = t;
+            previous_above = above;
+        }
+        None
+    }
+
+    /**
+     * Approximates the distance between two positions when travelling along the terrain surface.
+     *
+     * Each position is connected to the boundary vertices of its region and the shortest path
+     * is found over the terrain graph using 3D edge lengths.
+     *
+     * @return The distance or `None` if either position lies outside of the terrain.
+     */
+    pub fn geodesic_distance(&self, from: (f64, f64), to: (f64, f64)) -> Option<f64> {
+        let (from_region, to_region) = match (self.region_of(from.0, from.1), self.region_of(to.0, to.1)) {
+            (Some(from_region), Some(to_region)) => (from_region, to_region),
+            _ => return None,
+        };
+
+        let vertices = &self.terrain_graph.vertices;
+        let surface_point = |point: (f64, f64)| {
+            Point3::new(point.0, point.1, self.height_at(point.0, point.1).unwrap())
+        };
+        let from_point = surface_point(from);
+        let to_point = surface_point(to);
+
+        let sources: Vec<(usize, f64)> = self.region_graph.vertices[from_region].vertices
+            .iter()
+            .map(|&i| (i, (vertices[i].position - from_point).magnitude()))
+            .collect();
+
+        let (distances, _) = dijkstra(&sources, &self.terrain_graph.adjacency(), |a, b| {
+            (vertices[b].position - vertices[a].position).magnitude()
+        });
+
+        let mut distance = self.region_graph.vertices[to_region].vertices
+            .iter()
+            .map(|&i| distances[i] + (to_point - vertices[i].position).magnitude())
+            .fold(f64::INFINITY, f64::min);
+
+        // Positions in the same region can be connected directly.
+        if from_region == to_region {
+            distance = distance.min((to_point - from_point).magnitude());
+        }
+
+        Some(distance)
+    }
+
+    /**
+     * Rasterizes the region containing each pixel of a grid spanning the bounds of the sites.
+     *
+     * Pixels are sampled at their centers, with the first row at the minimum y.
+     *
+     * @return A row-major buffer of region indices, `u32::MAX` where outside of the terrain.
+     */
+    pub fn rasterize_region_ids(&self, width: u32, height: u32) -> Vec<u32> {
+        let bounds = self.site_bounds();
+        let mut ids: Vec<u32> = Vec::with_capacity(width as usize * height as usize);
+        for row in 0..height {
+            for column in 0..width {
+                let (x, y) = pixel_position(bounds, column, row, width, height);
+                ids.push(self.region_of(x, y).map_or(u32::MAX, |region| region as u32));
+            }
+        }
+        ids
+    }
+
+    /**
+     * Renders a square overview image of the terrain, coloring each region by its height.
+     *
+     * Regions below the water level are shaded blue by depth, land ramps from green to white.
+     *
+     * @return A row-major RGBA buffer, transparent where outside of the terrain.
+     */
+    pub fn minimap(&self, size: u32) -> Vec<[u8; 4]> {
+        let water_height = self.water_height();
+        let colors: Vec<[u8; 4]> = self.region_graph.vertices.iter()
+            .map(|region| {
+                let z = region.center.z;
+                if z < water_height {
+                    let t = ((z - NOISE_RANGE.0) / (water_height - NOISE_RANGE.0)).clamp(0.0, 1.0);
+                    [(20.0 + 40.0 * t) as u8, (40.0 + 80.0 * t) as u8, (120.0 + 100.0 * t) as u8, 255]
+                } else {
+                    let t = ((z - water_height) / (NOISE_RANGE.1 - water_height)).clamp(0.0, 1.0);
+                    [(60.0 + 195.0 * t) as u8, (140.0 + 115.0 * t) as u8, (60.0 + 195.0 * t) as u8, 255]
+                }
+            })
+            .collect();
+
+        self.rasterize_region_ids(size, size)
+            .into_iter()
+            .map(|id| if id == u32::MAX { [0, 0, 0, 0] } else { colors[id as usize] })
+            .collect()
+    }
+
+    /**
+     * Calculates the on-screen area of each region, e.g. for choosing which labels fit.
+     *
+     * The boundary of each region is projected into pixels, so the area accounts for the aspect
+     * ratio of the viewport. Regions with any boundary vertex behind the camera have no area.
+     *
+     * @param viewport The width and height of the viewport in pixels.
+     * @return The area of each region in square pixels, indexed by region.
+     */
+    pub fn region_screen_area(&self,
+                              view: &Matrix4<f64>,
+                              projection: &Matrix4<f64>,
+                              viewport: (u32, u32))
+                              -> Vec<f64> {
+        let view_projection = projection * view;
+        let vertices = &self.terrain_graph.vertices;
+
+        let project = |i: usize| {
+            let position = vertices[i].position;
+            let clip = view_projection * Vector4::new(position.x, position.y, position.z, 1.0);
+            if clip.w <= 0.0 {
+                return None;
+            }
+            Some(((clip.x / clip.w + 1.0) * 0.5 * viewport.0 as f64,
+                  (1.0 - clip.y / clip.w) * 0.5 * viewport.1 as f64))
+        };
+
+        self.region_graph.vertices.iter()
+            .map(|region| {
+                let polygon: Option<Vec<(f64, f64)>> = region.vertices.iter().map(|&i| project(i)).collect();
+                polygon.map_or(0.0, |polygon| polygon_area(&polygon))
+            })
+            .collect()
+    }
+
+    /**
+     * Finds the regions which may be visible to the camera, e.g. to skip drawing hidden regions.
+     *
+     * A region is potentially visible if its bounding box intersects the frustum.
+     *
+     * @return The potentially visible regions, nearest to the camera first.
+     */
+    pub fn potentially_visible_set(&self, camera_pos: Point3<f64>, frustum: &Frustum) -> Vec<usize> {
+        let regions = &self.region_graph.vertices;
+
+        let mut visible: Vec<(usize, f64)> = (0..regions.len())
+            .filter(|&region| {
+                let (min, max) = self.region_bounds(region);
+                frustum.intersects_aabb(min, max)
+            })
+            .map(|region| (region, (regions[region].center - camera_pos).magnitude2()))
+            .collect();
+
+        // Drawing front to back lets the depth test reject more of the hidden fragments.
+        visible.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap().then(a.0.cmp(&b.0)));
+        visible.into_iter().map(|(region, _)| region).collect()
+    }
+
+    /** The minimum and maximum corners of the bounding box of a region's boundary and center. */
+    fn region_bounds(&self, region: usize) -> (Point3<f64>, Point3<f64>) {
+        let vertices = &self.terrain_graph.vertices;
+        let region = &self.region_graph.vertices[region];
+
+        region.vertices.iter()
+            .map(|&i| vertices[i].position)
+            .fold((region.center, region.center), |(min, max), p| {
+                (Point3::new(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z)),
+                 Point3::new(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z)))
+            })
+    }
+
+    /**
+     * Writes the heights as an ESRI ASCII grid, e.g. for GIS tools.
+     *
+     * Cells are square, sized so the grid covers the bounds of the sites from its lower left
+     * corner. Heights are sampled at cell centers with rows written from north to south, cells
+     * outside of the terrain are written as the NODATA value.
+     */
+    pub fn write_asc<W: Write>(&self, w: &mut W, ncols: u32, nrows: u32) -> io::Result<()> {
+        let nodata = -9999.0;
+        let (min, max) = self.site_bounds();
+        let cellsize = ((max.0 - min.0) / ncols as f64).max((max.1 - min.1) / nrows as f64);
+
+        writeln!(w, "ncols {}", ncols)?;
+        writeln!(w, "nrows {}", nrows)?;
+        writeln!(w, "xllcorner {}", min.0)?;
+        writeln!(w, "yllcorner {}", min.1)?;
+        writeln!(w, "cellsize {}", cellsize)?;
+        writeln!(w, "NODATA_value {}", nodata)?;
+
+        for row in 0..nrows {
+            let y = min.1 + ((nrows - row) as f64 - 0.5) * cellsize;
+            let line: Vec<String> = (0..ncols)
+                .map(|column| {
+                    let x = min.0 + (column as f64 + 0.5) * cellsize;
+                    self.height_at(x, y).unwrap_or(nodata).to_string()
+                })
+                .collect();
+            writeln!(w, "{}", line.join(" "))?;
+        }
+        Ok(())
+    }
+
+    /** The minimum and maximum corners of the planar bounds of the sites. */
+    fn site_bounds(&self) -> ((f64, f64), (f64, f64)) {
+        point_bounds(&self.site_of_region)
+    }
+
+    /**
+     * The neighbouring regions of each region which share a border within the bounds of the sites.
+     *
+     * Regions on the hull are closed by vertices far outside of the sites, where hull regions
+     * which aren't otherwise near one another may share a border.
+     */
+    fn local_adjacency(&self) -> Vec<Vec<usize>> {
+        let (min, max) = self.site_bounds();
+        let vertices = &self.terrain_graph.vertices;
+        let regions = &self.region_graph.vertices;
+        let in_bounds = |v: usize| {
+            let position = vertices[v].position;
+            position.x >= min.0 && position.x <= max.0 && position.y >= min.1 && position.y <= max.1
+        };
+
+        self.region_adjacency()
+            .iter()
+            .enumerate()
+            .map(|(region, neighbours)| {
+                neighbours.iter()
+                    .cloned()
+                    .filter(|&neighbour| {
+                        regions[region].vertices
+                            .iter()
+                            .any(|&v| in_bounds(v) && regions[neighbour].vertices.contains(&v))
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /**
+     * Mirrors the terrain across a line to produce a symmetric terrain.
+     *
+     * Sites on the low side of the line are kept and reflected onto the high side, replacing
+     * any sites already there. Sites lying exactly on the line are kept but not duplicated.
+     *
+     * @param axis The axis whose coordinate is reflected.
+     * @param at The coordinate of the mirror line along the axis.
+     */
+    pub fn mirror(&mut self, axis: Axis, at: f64) {
+        let reflect = |(x, y): (f64, f64)| match axis {
+            Axis::X => (2.0 * at - x, y),
+            Axis::Y => (x, 2.0 * at - y),
+        };
+        let offset = |(x, y): (f64, f64)| match axis {
+            Axis::X => x - at,
+            Axis::Y => y - at,
+        };
+
+        let mut sites: Vec<(f64, f64)> = self.site_of_region.iter()
+            .cloned()
+            .filter(|&site| offset(site) <= 0.0)
+            .collect();
+        let reflected: Vec<(f64, f64)> = sites.iter()
+            .cloned()
+            .filter(|&site| offset(site) < 0.0)
+            .map(&reflect)
+            .collect();
+        sites.extend(reflected);
+
+        // Sample the existing terrain with positions folded onto the kept side.
+        *self = self.resample(sites, |x, y| if offset((x, y)) > 0.0 { reflect((x, y)) } else { (x, y) });
+    }
+
+    /**
+     * Extracts the vertical strip of the terrain whose region centers lie within [x0, x1], e.g.
+     * for infinite scrolling.
+     *
+     * Regions, terrain vertices and edges are renumbered, keeping only those the strip uses.
+     */
+    pub fn strip(&self, x0: f64, x1: f64) -> VoronoiTerrain {
+        let keep: Vec<bool> = self.region_graph.vertices
+            .iter()
+            .map(|region| region.center.x >= x0 && region.center.x <= x1)
+            .collect();
+        let (terrain_graph, region_graph) = retain_regions(&self.terrain_graph, &self.region_graph, &keep);
+        let sites = self.site_of_region
+            .iter()
+            .zip(keep.iter())
+            .filter(|&(_, &keep)| keep)
+            .map(|(&site, _)| site)
+            .collect();
+
+        VoronoiTerrain {
+            terrain_graph: terrain_graph,
+            region_graph: region_graph,
+            site_of_region: sites,
+            water_level: self.water_level,
+            height: self.height,
+            out_of_bounds_height: self.out_of_bounds_height,
+            region_adjacency: OnceLock::new(),
+            regions_by_vertex: OnceLock::new(),
+        }
+    }
+
+    /**
+     * Generates a coarser level of detail of the terrain.
+     *
+     * Each level contracts the region graph, merging each region with its nearest unmerged
+     * neighbour into a single site at their midpoint, roughly halving the number of regions.
+     * Heights are sampled from this terrain so the overall shape is preserved.
+     *
+     * @param level The number of contractions, 0 returns an equivalent terrain.
+     */
+    pub fn lod(&self, level: u32) -> VoronoiTerrain {
+        let mut sites = self.site_of_region.clone();
+        let mut adjacency = self.region_adjacency().to_vec();
+
+        for _ in 0..level {
+            let mut merged: Vec<bool> = vec![false; sites.len()];
+            let mut contracted: Vec<(f64, f64)> = Vec::with_capacity(sites.len() / 2 + 1);
+
+            for site in 0..sites.len() {
+                if merged[site] {
+                    continue;
+                }
+                merged[site] = true;
+
+                let nearest = adjacency[site].iter()
+                    .cloned()
+                    .filter(|&neighbour| !merged[neighbour])
+                    .fold(None, |nearest: Option<usize>, neighbour| match nearest {
+                        Some(nearest) if distance2(sites[site], sites[nearest]) <=
+                                         distance2(sites[site], sites[neighbour]) => Some(nearest),
+                        _ => Some(neighbour),
+                    });
+
+                match nearest {
+                    Some(neighbour) => {
+                        merged[neighbour] = true;
+                        contracted.push(((sites[site].0 + sites[neighbour].0) * 0.5,
+                                         (sites[site].1 + sites[neighbour].1) * 0.5));
+                    },
+                    None => contracted.push(sites[site]),
+                }
+            }
+
+            // Stop once nothing can be merged, e.g. a single region.
+            if contracted.len() == sites.len() {
+                break;
+            }
+            sites = contracted;
+
+            // Triangulate the contracted sites to find their adjacency for the next level.
+            let mut dt = Delaunay2D::new((0.0, 0.0), 9999.0);
+            for site in sites.iter() {
+                dt.add_point(*site);
+            }
+            let (_, dt_cells) = voronoi_regions(&dt);
+            adjacency = voronoi_adjacency(&dt_cells);
+        }
+
+        self.resample(sites, |x, y| (x, y))
+    }
+
+    /**
+     * Builds a new terrain from a set of sites with heights sampled from this terrain.
+     *
+     * @param sites The sites of the new terrain.
+     * @param position Maps positions in the new terrain to the positions they're sampled at.
+     */
+    fn resample<F>(&self, sites: Vec<(f64, f64)>, position: F) -> VoronoiTerrain
+        where F: Fn(f64, f64) -> (f64, f64)
+    {
+        let height = |x: f64, y: f64| {
+            let (x, y) = position(x, y);
+            self.height_at(x, y)
+                .or_else(|| self.nearest_region(x, y).map(|region| self.region_graph.vertices[region].center.z))
+                .unwrap_or(0.0)
+        };
+        let (terrain_graph, region_graph) =
+            triangulate(&sites, height, None).expect("Triangulation without a deadline can't time out");
+
+        VoronoiTerrain {
+            terrain_graph: terrain_graph,
+            region_graph: region_graph,
+            site_of_region: sites,
+            water_level: self.water_level,
+            height: self.height,
+            out_of_bounds_height: self.out_of_bounds_height,
+            region_adjacency: OnceLock::new(),
+            regions_by_vertex: OnceLock::new(),
+        }
+    }
+
+    /**
+     * Bakes the interpolated surface normal over a grid spanning the bounds of the sites.
+     *
+     * Normals are encoded as RGB with each component mapped from [-1, 1] to [0, 255], positions
+     * outside of the terrain encode the up normal. Rows are laid out as in `rasterize_region_ids`.
+     */
+    pub fn bake_normal_map(&self, width: u32, height: u32) -> Vec<[u8; 3]> {
+        let encode = |component: f64| ((component + 1.0) * 0.5 * 255.0).round().clamp(0.0, 255.0) as u8;
+
+        let bounds = self.site_bounds();
+        let mut pixels: Vec<[u8; 3]> = Vec::with_capacity(width as usize * height as usize);
+        for row in 0..height {
+            for column in 0..width {
+                let (x, y) = pixel_position(bounds, column, row, width, height);
+                let normal = self.normal_at(x, y).unwrap_or(Vector3::new(0.0, 0.0, 1.0));
+                pixels.push([encode(normal.x), encode(normal.y), encode(normal.z)]);
+            }
+        }
+        pixels
+    }
+
+    /** Interpolates the surface normal at a position, `None` if outside of the terrain. */
+    fn normal_at(&self, x: f64, y: f64) -> Option<Vector3<f64>> {
+        self.locate(x, y).map(|(region_index, i, (wc, w0, w1))| {
+            let region = &self.region_graph.vertices[region_index];
+            let vertices = &self.terrain_graph.vertices;
+            let v0 = region.vertices[i];
+            let v1 = region.vertices[(i + 1) % region.vertices.len()];
+            let normal = region.normal * wc + vertices[v0].normal * w0 + vertices[v1].normal * w1;
+            if normal != Vector3::zero() { normal.normalize() } else { region.normal }
+        })
+    }
+
+    /**
+     * Finds an anchor point for a region's label.
+     *
+     * Uses the pole of inaccessibility, the point inside the region furthest from its boundary,
+     * since the centroid of a non-convex region can fall outside of it. The pole is found by
+     * repeatedly searching a grid around the best point so far with a finer spacing.
+     */
+    pub fn label_anchor(&self, region: usize) -> Point3<f64> {
+        let polygon = self.region_polygon(region);
+        let center = self.region_graph.vertices[region].center;
+
+        let (min, max) = polygon.iter().fold(
+            ((f64::INFINITY, f64::INFINITY), (f64::NEG_INFINITY, f64::NEG_INFINITY)),
+            |(min, max), &p| ((min.0.min(p.0), min.1.min(p.1)), (max.0.max(p.0), max.1.max(p.1))));
+
+        let boundary_distance = |point: (f64, f64)| {
+            (0..polygon.len())
+                .map(|i| segment_distance2(point, polygon[i], polygon[(i + 1) % polygon.len()]))
+                .fold(f64::INFINITY, f64::min)
+        };
+
+        const SAMPLES: i32 = 8;
+        const ITERATIONS: i32 = 6;
+
+        let mut best = ((min.0 + max.0) * 0.5, (min.1 + max.1) * 0.5);
+        if !point_in_polygon(best, &polygon) {
+            best = (center.x, center.y);
+        }
+        let mut best_distance = if point_in_polygon(best, &polygon) { boundary_distance(best) } else { 0.0 };
+        let mut spacing = ((max.0 - min.0).max(max.1 - min.1)) / SAMPLES as f64;
+
+        for _ in 0..ITERATIONS {
+            let origin = best;
+            for i in -SAMPLES..SAMPLES + 1 {
+                for j in -SAMPLES..SAMPLES + 1 {
+                    let point = (origin.0 + i as f64 * spacing * 0.5, origin.1 + j as f64 * spacing * 0.5);
+                    if !point_in_polygon(point, &polygon) {
+                        continue;
+                    }
+                    let distance = boundary_distance(point);
+                    if distance > best_distance {
+                        best = point;
+                        best_distance = distance;
+                    }
+                }
+            }
+            spacing /= SAMPLES as f64 * 0.5;
+        }
+
+        let height = self.height_at(best.0, best.1).unwrap_or(center.z);
+        Point3::new(best.0, best.1, height)
+    }
+
+    /**
+     * Calculates the centroid of only the above water part of a region, e.g. for labels.
+     *
+     * The region's boundary is clipped where it crosses the water height, interpolating linearly
+     * between its boundary vertices.
+     *
+     * @return The centroid or `None` if the region is fully submerged.
+     */
+    pub fn land_centroid(&self, region: usize) -> Option<Point3<f64>> {
+        let water_height = self.water_height();
+        let vertices = &self.terrain_graph.vertices;
+        let boundary: Vec<Point3<f64>> = self.region_graph.vertices[region].vertices
+            .iter()
+            .map(|&i| vertices[i].position)
+            .collect();
+
+        // Clip the boundary against the water plane with Sutherland-Hodgman.
+        let mut land: Vec<(f64, f64)> = Vec::with_capacity(boundary.len() + 2);
+        for i in 0..boundary.len() {
+            let a = boundary[i];
+            let b = boundary[(i + 1) % boundary.len()];
+            if a.z >= water_height {
+                land.push((a.x, a.y));
+            }
+            if (a.z >= water_height) != (b.z >= water_height) {
+                let t = (water_height - a.z) / (b.z - a.z);
+                land.push((a.x + (b.x - a.x) * t, a.y + (b.y - a.y) * t));
+            }
+        }
+        if land.is_empty() {
+            return None;
+        }
+
+        let (x, y) = polygon_centroid(&land);
+        let height = self.height_at(x, y).unwrap_or(water_height).max(water_height);
+        Some(Point3::new(x, y, height))
+    }
+
+    /**
+     * Locates the triangle containing a position within the fan triangulation of its region.
+     *
+     * Each region is triangulated as a fan of (center, vertices[i], vertices[i + 1]).
+     *
+     * @return The region index, boundary vertex offset `i` and the barycentric weights of
+     *         the center, vertices[i] and vertices[i + 1].
+     */
+    fn locate(&self, x: f64, y: f64) -> Option<(usize, usize, (f64, f64, f64))> {
+        let region_index = self.region_of(x, y)?;
+
+        let region = &self.region_graph.vertices[region_index];
+        let vertices = &self.terrain_graph.vertices;
+        let center = (region.center.x, region.center.y);
+
+        for i in 0..region.vertices.len() {
+            let p0 = vertices[region.vertices[i]].position;
+            let p1 = vertices[region.vertices[(i + 1) % region.vertices.len()]].position;
+            if let Some(weights) = barycentric((x, y), center, (p0.x, p0.y), (p1.x, p1.y)) {
+                return Some((region_index, i, weights));
+            }
+        }
+
+        // The position is inside the region but missed each triangle due to precision.
+        Some((region_index, 0, (1.0, 0.0, 0.0)))
+    }
+
+    /** The planar boundary polygon of a region. */
+    fn region_polygon(&self, region: usize) -> Vec<(f64, f64)> {
+        self.region_graph.vertices[region].vertices
+            .iter()
+            .map(|&i| {
+                let position = self.terrain_graph.vertices[i].position;
+                (position.x, position.y)
+            })
+            .collect()
+    }
+
+    /** Lists the unique region edges with the lower region index first, in sorted order. */
+    fn delaunay_edges(&self) -> Vec<(usize, usize)> {
+        let mut edges: Vec<(usize, usize)> = self.region_graph.edges
+            .iter()
+            .map(|&(a, b)| if a < b { (a, b) } else { (b, a) })
+            .collect();
+        edges.sort();
+        edges.dedup();
+        edges
+    }
+
+    /**
+     * Finds the saddle points of the terrain, such as mountain passes.
+     *
+     * The ring of vertices surrounding each vertex, formed by the boundaries of its regions, is
+     * walked in angular order. A vertex is a saddle if the ring alternates between higher and
+     * lower than the vertex at least twice, i.e. two higher areas are separated by two lower ones.
+     *
+     * @return The indices of the saddle terrain vertices.
+     */
+    pub fn saddle_points(&self) -> Vec<usize> {
+        let vertices = &self.terrain_graph.vertices;
+        let regions = &self.region_graph.vertices;
+
+        self.regions_by_vertex()
+            .iter()
+            .enumerate()
+            .filter(|&(vertex_index, vertex_regions)| {
+                let position = vertices[vertex_index].position;
+
+                let mut ring: Vec<usize> = vertex_regions.iter()
+                    .flat_map(|&region| regions[region].vertices.iter().cloned())
+                    .filter(|&i| i != vertex_index)
+                    .collect();
+                ring.sort();
+                ring.dedup();
+
+                let angle = |i: usize| {
+                    let offset = vertices[i].position - position;
+                    offset.y.atan2(offset.x)
+                };
+                ring.sort_by(|&a, &b| angle(a).partial_cmp(&angle(b)).unwrap());
+
+                let higher: Vec<bool> = ring.iter()
+                    .map(|&i| vertices[i].position.z >= position.z)
+                    .collect();
+                let sign_changes = (0..higher.len())
+                    .filter(|&i| higher[i] != higher[(i + 1) % higher.len()])
+                    .count();
+
+                sign_changes >= 4
+            })
+            .map(|(vertex_index, _)| vertex_index)
+            .collect()
+    }
+
+    /**
+     * Calculates a tangent for each terrain vertex, for use in normal mapping.
+     *
+     * Texture coordinates are assumed to be planar, with u along x and v along y.
+     *
+     * @return The unit tangent of each vertex, orthogonal to the vertex normal.
+     */
+    pub fn compute_tangents(&self) -> Vec<Vector3<f64>> {
+        let vertices = &self.terrain_graph.vertices;
+        let mut tangents: Vec<Vector3<f64>> = vec![Vector3::zero(); vertices.len()];
+
+        for triangle in self.triangles().iter() {
+            let p0 = vertices[triangle[0]].position;
+            let e1 = vertices[triangle[1]].position - p0;
+            let e2 = vertices[triangle[2]].position - p0;
+
+            // Solve for the direction of increasing u given the planar (x, y) texture coordinates.
+            let det = e1.x * e2.y - e2.x * e1.y;
+            if det == 0.0 {
+                continue;
+            }
+            let tangent = (e1 * e2.y - e2 * e1.y) / det;
+
+            for &i in triangle.iter() {
+                tangents[i] += tangent;
+            }
+        }
+
+        tangents.iter()
+            .zip(vertices.iter())
+            .map(|(&tangent, vertex)| {
+                let normal = vertex.normal;
+                let tangent = if tangent == Vector3::zero() { Vector3::new(1.0, 0.0, 0.0) } else { tangent };
+
+                // Gram-Schmidt orthogonalize against the normal.
+                let orthogonal = tangent - normal * normal.dot(tangent);
+                if orthogonal.magnitude2() > 0.0 {
+                    orthogonal.normalize()
+                } else {
+                    orthogonal
+                }
+            })
+            .collect()
+    }
+
+    /**
+     * Generates planar texture coordinates for each terrain vertex.
+     *
+     * @return The (x, y) position of each vertex mapped to [0, 1] by the bounds of the sites.
+     *         Vertices of the outer regions lying beyond the sites are clamped to the edges.
+     */
+    pub fn uvs(&self) -> Vec<[f32; 2]> {
+        let (min, max) = self.site_bounds();
+
+        // Avoid dividing by zero for degenerate terrains.
+        let extent = ((max.0 - min.0).max(f64::EPSILON), (max.1 - min.1).max(f64::EPSILON));
+
+        self.terrain_graph.vertices
+            .iter()
+            .map(|vertex| {
+                [((vertex.position.x - min.0) / extent.0).clamp(0.0, 1.0) as f32,
+                 ((vertex.position.y - min.1) / extent.1).clamp(0.0, 1.0) as f32]
+            })
+            .collect()
+    }
+
+    /**
+     * Lists the triangles of the fan triangulated mesh, see `triangles`, which include a vertex.
+     */
+    pub fn vertex_triangles(&self, v: usize) -> Vec<[usize; 3]> {
+        self.regions_by_vertex()[v].iter()
+            .flat_map(|&region_index| fan_triangles(&self.region_graph.vertices[region_index].vertices))
+            .filter(|triangle| triangle.contains(&v))
+            .collect()
+    }
+
+    /**
+     * Splits the fan triangulated mesh, see `triangles`, into separate land and water meshes.
+     *
+     * Regions with centers below the water height make up the water mesh. Vertices shared by land
+     * and water regions are duplicated into both meshes.
+     *
+     * @return The land and water meshes.
+     */
+    pub fn split_meshes(&self) -> (Mesh, Mesh) {
+        let water_height = self.water_height();
+        (self.mesh_of_regions(|region| region.center.z >= water_height),
+         self.mesh_of_regions(|region| region.center.z < water_height))
+    }
+
+    /** Builds a single mesh from the fan triangulated mesh, see `triangles`, of every region. */
+    pub fn triangulated_mesh(&self) -> Mesh {
+        self.mesh_of_regions(|_| true)
+    }
+
+    /** Builds a standalone mesh from the fan triangulation of the regions matching a filter. */
+    fn mesh_of_regions<F>(&self, filter: F) -> Mesh
+        where F: Fn(&Region) -> bool
+    {
+        let vertices = &self.terrain_graph.vertices;
+        let uvs = self.uvs();
+        let mut mesh = Mesh {
+            positions: Vec::new(),
+            normals: Vec::new(),
+            colors: Vec::new(),
+            uvs: Vec::new(),
+            indices: Vec::new(),
+        };
+
+        let mut mesh_index_by_vertex: HashMap<usize, u32> = HashMap::new();
+        for region in self.region_graph.vertices.iter().filter(|region| filter(region)) {
+            for triangle in fan_triangles(&region.vertices).iter() {
+                for &vertex_index in triangle.iter() {
+                    let mesh_index = *mesh_index_by_vertex.entry(vertex_index).or_insert_with(|| {
+                        let vertex = &vertices[vertex_index];
+                        mesh.positions.push([vertex.position.x as f32,
+                                             vertex.position.y as f32,
+                                             vertex.position.z as f32]);
+                        mesh.normals.push([vertex.normal.x as f32, vertex.normal.y as f32, vertex.normal.z as f32]);
+                        mesh.colors.push(vertex.color);
+                        mesh.uvs.push(uvs[vertex_index]);
+                        (mesh.positions.len() - 1) as u32
+                    });
+                    mesh.indices.push(mesh_index);
+                }
+            }
+        }
+        mesh
+    }
+
+    /**
+     * Sets the color of every terrain vertex from a function of the vertex, e.g. to bake height
+     * coloring or ambient occlusion into the meshes.
+     */
+    pub fn colorize<F>(&mut self, f: F)
+        where F: Fn(&TerrainVertex) -> [f32; 4]
+    {
+        for vertex in self.terrain_graph.vertices.iter_mut() {
+            vertex.color = f(vertex);
+        }
+    }
+
+    /**
+     * Calculates the Euler characteristic, V - E + F, of the terrain as a mesh health check.
+     *
+     * Regions are counted as faces, fan triangulating them adds as many edges as faces so the
+     * result is the same as for the triangulated mesh. A correctly built terrain has the topology
+     * of a disk and a characteristic of 1.
+     */
+    pub fn euler_characteristic(&self) -> i64 {
+        self.terrain_graph.vertices.len() as i64 - self.terrain_graph.edges.len() as i64 +
+        self.region_graph.vertices.len() as i64
+    }
+
+    /**
+     * Triangulates each region as a fan from its first boundary vertex.
+     *
+     * @return Triangles of terrain vertex indices.
+     */
+    pub fn triangles(&self) -> Vec<[usize; 3]> {
+        self.region_graph.vertices.iter()
+            .flat_map(|region| fan_triangles(&region.vertices))
+            .collect()
+    }
+
+    /**
+     * Splits the terrain surface into filled bands of elevation, e.g. for layered topographic maps.
+     *
+     * Each surface triangle is clipped against the bounding heights of every band it spans, so the
+     * bands cover the footprint of the terrain without overlap. Pieces are not merged across
+     * triangles.
+     *
+     * @param step The height of each band, bands start at multiples of the step.
+     * @return The lower height of each non-empty band and its planar polygons, lowest band first.
+     */
+    pub fn elevation_bands(&self, step: f64) -> Vec<(f64, Vec<Polygon>)> {
+        if step <= 0.0 {
+            return Vec::new();
+        }
+        let vertices = &self.terrain_graph.vertices;
+
+        let mut bands: HashMap<i64, Vec<Polygon>> = HashMap::new();
+        for triangle in self.triangles().into_iter() {
+            let triangle: Vec<Point3<f64>> = triangle.iter().map(|&i| vertices[i].position).collect();
+            let min = triangle.iter().fold(f64::INFINITY, |min, p| min.min(p.z));
+            let max = triangle.iter().fold(f64::NEG_INFINITY, |max, p| max.max(p.z));
+
+            for band in (min / step).floor() as i64..(max / step).floor() as i64 + 1 {
+                let low = band as f64 * step;
+                let piece = clip_polygon_by_height(&triangle, low, true);
+                let piece = clip_polygon_by_height(&piece, low + step, false);
+                if piece.len() >= 3 {
+                    bands.entry(band)
+                        .or_default()
+                        .push(piece.iter().map(|p| (p.x, p.y)).collect());
+                }
+            }
+        }
+
+        let mut bands: Vec<(i64, Vec<Polygon>)> = bands.into_iter().collect();
+        bands.sort_by_key(|&(band, _)| band);
+        bands.into_iter().map(|(band, polygons)| (band as f64 * step, polygons)).collect()
+    }
+
+    /**
+     * Calculates the topographic prominence of each peak.
+     *
+     * Vertices are added from highest to lowest, joining their already added neighbours with a
+     * union-find. A vertex joining two components is the highest saddle between them, at which
+     * point the lower component's peak gets its prominence. The highest peak's prominence is its
+     * height above the lowest vertex.
+     *
+     * The lower component is always joined to the higher one, so the root of each component is
+     * its peak.
+     *
+     * @return Each local maximum vertex and its prominence, most prominent first.
+     */
+    pub fn peak_prominence(&self) -> Vec<(usize, f64)> {
+        let vertices = &self.terrain_graph.vertices;
+        let adjacency = self.terrain_graph.adjacency();
+        let height = |i: usize| vertices[i].position.z;
+
+        let mut order: Vec<usize> = (0..vertices.len()).collect();
+        order.sort_by(|&a, &b| height(b).partial_cmp(&height(a)).unwrap());
+
+        let mut parent: Vec<usize> = (0..vertices.len()).collect();
+        let mut added: Vec<bool> = vec![false; vertices.len()];
+        let mut prominence: Vec<(usize, f64)> = Vec::new();
+
+        for &vertex_index in order.iter() {
+            added[vertex_index] = true;
+
+            for &neighbour in adjacency[vertex_index].iter() {
+                if !added[neighbour] {
+                    continue;
+                }
+
+                let root = find_root(&mut parent, vertex_index);
+                let neighbour_root = find_root(&mut parent, neighbour);
+                if root == neighbour_root {
+                    continue;
+                }
+
+                // The vertex itself is only a peak if it's higher than its component's peak.
+                let (higher, lower) = if height(root) >= height(neighbour_root) {
+                    (root, neighbour_root)
+                } else {
+                    (neighbour_root, root)
+                };
+                if lower != vertex_index {
+                    prominence.push((lower, height(lower) - height(vertex_index)));
+                }
+                parent[lower] = higher;
+            }
+        }
+
+        // The remaining components' peaks have no higher terrain.
+        if let Some(&lowest) = order.last() {
+            for i in 0..vertices.len() {
+                if find_root(&mut parent, i) == i {
+                    prominence.push((i, height(i) - height(lowest)));
+                }
+            }
+        }
+
+        prominence.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+        prominence
+    }
+
+    /**
+     * Traces the ridge lines of the terrain.
+     *
+     * Ridge vertices are higher than the terrain on both sides of them across some direction, so
+     * ramps and plains have none. The sides are the vertices sharing a region with the vertex
+     * within 45 degrees of either way along one of 4 directions. Vertices outside of the bounds
+     * of the sites are ignored, as the outer regions are stretched far beyond them.
+     *
+     * Chains are grown from the highest remaining ridge vertex by repeatedly stepping to the
+     * highest unvisited neighbouring ridge vertex, in both directions. A single non-ridge vertex
+     * may be bridged, as crests bend between vertices. Ridge vertices beside a chain lie on the
+     * same crest so don't start chains of their own.
+     *
+     * @return Chains of at least 2 terrain vertex indices.
+     */
+    pub fn ridge_lines(&self) -> Vec<Vec<usize>> {
+        const DIRECTIONS: usize = 4;
+        // The drop required on both sides, ignoring rounding in the heights.
+        const EPSILON: f64 = 1e-9;
+
+        let vertices = &self.terrain_graph.vertices;
+        let regions = &self.region_graph.vertices;
+        let adjacency = self.terrain_graph.adjacency();
+        let height = |i: usize| vertices[i].position.z;
+        let (min, max) = self.site_bounds();
+
+        let is_ridge: Vec<bool> = self.regions_by_vertex()
+            .iter()
+            .enumerate()
+            .map(|(vertex_index, vertex_regions)| {
+                let position = vertices[vertex_index].position;
+                if position.x < min.0 || position.x > max.0 || position.y < min.1 || position.y > max.1 {
+                    return false;
+                }
+
+                let mut ring: Vec<usize> = vertex_regions.iter()
+                    .flat_map(|&region| regions[region].vertices.iter().cloned())
+                    .filter(|&i| i != vertex_index)
+                    .collect();
+                ring.sort();
+                ring.dedup();
+
+                (0..DIRECTIONS).any(|direction| {
+                    let angle = direction as f64 / DIRECTIONS as f64 * std::f64::consts::PI;
+                    let (dx, dy) = (angle.cos(), angle.sin());
+
+                    let mut sides = (false, false);
+                    for &i in ring.iter() {
+                        let offset = (vertices[i].position - position).truncate();
+                        let along = (offset.x * dx + offset.y * dy) / offset.magnitude();
+                        if along.abs() < std::f64::consts::FRAC_1_SQRT_2 {
+                            continue;
+                        }
+                        if height(i) >= position.z - EPSILON {
+                            return false;
+                        }
+                        if along > 0.0 {
+                            sides.0 = true;
+                        } else {
+                            sides.1 = true;
+                        }
+                    }
+                    sides.0 && sides.1
+                })
+            })
+            .collect();
+
+        let mut order: Vec<usize> = (0..vertices.len()).filter(|&i| is_ridge[i]).collect();
+        order.sort_by(|&a, &b| height(b).partial_cmp(&height(a)).unwrap());
+
+        let mut visited: Vec<bool> = vec![false; vertices.len()];
+        let mut chains: Vec<Vec<usize>> = Vec::new();
+        for &start in order.iter() {
+            if visited[start] {
+                continue;
+            }
+            visited[start] = true;
+
+            let walk = |visited: &mut Vec<bool>| {
+                let mut chain: Vec<usize> = Vec::new();
+                let mut current = start;
+                loop {
+                    let highest = |candidates: &mut dyn Iterator<Item = usize>| {
+                        candidates.fold(None, |highest: Option<usize>, neighbour| match highest {
+                            Some(highest) if height(highest) >= height(neighbour) => Some(highest),
+                            _ => Some(neighbour),
+                        })
+                    };
+                    let next = highest(&mut adjacency[current].iter()
+                            .cloned()
+                            .filter(|&neighbour| is_ridge[neighbour] && !visited[neighbour]))
+                        .or_else(|| highest(&mut adjacency[current].iter()
+                            .cloned()
+                            .filter(|&neighbour| !visited[neighbour])
+                            .filter(|&neighbour| {
+                                adjacency[neighbour].iter()
+                                    .any(|&i| i != current && is_ridge[i] && !visited[i])
+                            })));
+                    match next {
+                        Some(next) => {
+                            visited[next] = true;
+                            chain.push(next);
+                            current = next;
+                        },
+                        None => return chain,
+                    }
+                }
+            };
+
+            let mut chain: Vec<usize> = walk(&mut visited);
+            chain.reverse();
+            chain.push(start);
+            chain.extend(walk(&mut visited));
+
+            for &i in chain.iter() {
+                for &neighbour in adjacency[i].iter() {
+                    visited[neighbour] = true;
+                }
+            }
+
+            if chain.len() >= 2 {
+                chains.push(chain);
+            }
+        }
+        chains
+    }
+
+    /**
+     * Finds the drainage basin of an outlet, every region whose water flows through it.
+     *
+     * Each region drains into its lowest neighbour, the basin is found by walking this flow graph
+     * upstream from the outlet.
+     *
+     * @return The regions of the basin including the outlet, in ascending order.
+     */
+    pub fn catchment(&self, outlet_region: usize) -> Vec<usize> {
+        let mut upstream: Vec<Vec<usize>> = vec![Vec::new(); self.region_graph.vertices.len()];
+        for (region, target) in self.region_flow_targets().into_iter().enumerate() {
+            if let Some(target) = target {
+                upstream[target].push(region);
+            }
+        }
+
+        let mut basin = vec![outlet_region];
+        let mut queue: VecDeque<usize> = VecDeque::new();
+        queue.push_back(outlet_region);
+        while let Some(region) = queue.pop_front() {
+            // Flow only runs downhill so the graph is acyclic and no region is reached twice.
+            for &source in upstream[region].iter() {
+                basin.push(source);
+                queue.push_back(source);
+            }
+        }
+        basin.sort();
+        basin
+    }
+
+    /**
+     * Labels the watershed basin of each region by the region its water finally drains into.
+     *
+     * Each region drains into its lowest neighbour, following these downhill ends at a local
+     * minimum which all of the regions of its basin share.
+     *
+     * @return The sink region of each region's basin, indexed by region.
+     */
+    pub fn watersheds(&self) -> Vec<usize> {
+        let flow_targets = self.region_flow_targets();
+        let mut sinks: Vec<Option<usize>> = vec![None; flow_targets.len()];
+
+        for start in 0..flow_targets.len() {
+            // Walk downhill until reaching a sink or a region already labelled.
+            let mut path: Vec<usize> = Vec::new();
+            let mut current = start;
+            let sink = loop {
+                if let Some(sink) = sinks[current] {
+                    break sink;
+                }
+                path.push(current);
+                match flow_targets[current] {
+                    Some(target) => current = target,
+                    None => break current,
+                }
+            };
+            for region in path.into_iter() {
+                sinks[region] = Some(sink);
+            }
+        }
+
+        sinks.into_iter().map(|sink| sink.unwrap()).collect()
+    }
+
+    /**
+     * Finds the divides between watershed basins, e.g. for drawing ridgelines or borders.
+     *
+     * @return The indices of the region edges whose regions lie in different basins.
+     */
+    pub fn watershed_divides(&self) -> Vec<usize> {
+        let watersheds = self.watersheds();
+        self.region_graph.edges
+            .iter()
+            .enumerate()
+            .filter(|&(_, &(a, b))| watersheds[a] != watersheds[b])
+            .map(|(edge_index, _)| edge_index)
+            .collect()
+    }
+
+    /**
+     * Finds pits which trap drainage, candidates for lake filling.
+     *
+     * Following each region's lowest neighbour downhill ends at a local minimum. Minima which are
+     * underwater or on the boundary of the terrain drain away, the remaining ones are pits.
+     *
+     * @return The indices of the pit regions.
+     */
+    pub fn drainage_pits(&self) -> Vec<usize> {
+        let water_height = self.water_height();
+        let regions = &self.region_graph.vertices;
+        let is_boundary = self.boundary_regions();
+
+        self.region_flow_targets()
+            .iter()
+            .enumerate()
+            .filter(|&(region, target)| {
+                target.is_none() && !is_boundary[region] && regions[region].center.z >= water_height
+            })
+            .map(|(region, _)| region)
+            .collect()
+    }
+
+    /** Finds the lowest neighbour of each region, if it's lower than the region itself. */
+    fn region_flow_targets(&self) -> Vec<Option<usize>> {
+        let regions = &self.region_graph.vertices;
+        self.region_adjacency()
+            .iter()
+            .enumerate()
+            .map(|(region, neighbours)| {
+                let mut target = None;
+                let mut lowest = regions[region].center.z;
+                for &neighbour in neighbours.iter() {
+                    if regions[neighbour].center.z < lowest {
+                        lowest = regions[neighbour].center.z;
+                        target = Some(neighbour);
+                    }
+                }
+                target
+            })
+            .collect()
+    }
+
+    /** Whether each region lies on the boundary of the terrain, having an edge with no neighbour. */
+    fn boundary_regions(&self) -> Vec<bool> {
+        let adjacency = self.region_adjacency();
+        self.region_graph.vertices
+            .iter()
+            .zip(adjacency.iter())
+            .map(|(region, neighbours)| neighbours.len() < region.vertices.len())
+            .collect()
+    }
+
+    /** Finds the lowest neighbour of each terrain vertex, if it's lower than the vertex itself. */
+    fn terrain_flow_targets(&self) -> Vec<Option<usize>> {
+        let vertices = &self.terrain_graph.vertices;
+        self.terrain_graph.adjacency()
+            .iter()
+            .enumerate()
+            .map(|(vertex_index, neighbours)| {
+                let mut target = None;
+                let mut lowest = vertices[vertex_index].position.z;
+                for &neighbour in neighbours.iter() {
+                    if vertices[neighbour].position.z < lowest {
+                        lowest = vertices[neighbour].position.z;
+                        target = Some(neighbour);
+                    }
+                }
+                target
+            })
+            .collect()
+    }
+}
+
+/** A planar polygon, its vertices in order. */
+pub type Polygon = Vec<(f64, f64)>;
+
+/** Adjusts a terrain vertex, given its index, after it's built. */
+type VertexPostProcess = dyn Fn(usize, &mut TerrainVertex);
+
+pub struct VoronoiTerrainBuilder {
+    seed: usize,
+    water_level: u32,
+    out_of_bounds_height: Option<f64>,
+    height: u32,
+    height_feedback: f64,
+    // The center, band height and band width of concentric elevation bands.
+    concentric_elevation: Option<((f64, f64), f64, f64)>,
+    build_timeout: Option<Duration>,
+    sort_vertex_edges: bool,
+    vertex_post_process: Option<Box<VertexPostProcess>>,
+    // The desired number of sites per unit area at a height.
+    adaptive_density: Option<Box<dyn Fn(f64) -> f64>>,
+    flat: bool,
+    // The position, height and radius of each control point.
+    control_points: Vec<(f64, f64, f64, f64)>,
+    boundary_margin: f64,
+    sites: Vec<(f64, f64)>,
+    // Generates the sites in place of the set sites, if any.
+    site_sampler: Option<SiteSampler>,
+    // The exact height of each site, replacing noise, if any.
+    site_heights: Option<Vec<f64>>,
+    // The polygon outside of which regions are dropped, if any.
+    mask_polygon: Option<Vec<(f64, f64)>>,
+    continent_layout: Option<ContinentLayout>,
+}
+
+impl VoronoiTerrainBuilder {
+    fn new() -> VoronoiTerrainBuilder {
+        let mut rng = rand::thread_rng();
+        VoronoiTerrainBuilder {
+            seed: rng.gen::<usize>(),
+            water_level: 50,
+            out_of_bounds_height: None,
+            height: 100,
+            height_feedback: 0.0,
+            concentric_elevation: None,
+            build_timeout: None,
+            sort_vertex_edges: false,
+            vertex_post_process: None,
+            adaptive_density: None,
+            flat: false,
+            control_points: Vec::new(),
+            boundary_margin: 0.0,
+            sites: Vec::new(),
+            site_sampler: None,
+            site_heights: None,
+            mask_polygon: None,
+            continent_layout: None,
+        }
+    }
+
+    pub fn set_seed(&mut self, seed: usize) -> &mut VoronoiTerrainBuilder {
+        self.seed = seed;
+        self
+    }
+
+    pub fn set_sites(&mut self, sites: Vec<(f64, f64)>) -> &mut VoronoiTerrainBuilder {
+        self.sites = sites;
+        self.site_sampler = None;
+        self.site_heights = None;
+        self
+    }
+
+    /**
+     * Sets a sampler which generates the sites, replacing any set sites.
+     *
+     * Sampling is seeded by the builder's seed so builds remain deterministic.
+     */
+    pub fn set_site_sampler(&mut self, site_sampler: SiteSampler) -> &mut VoronoiTerrainBuilder {
+        self.site_sampler = Some(site_sampler);
+        self.site_heights = None;
+        self
+    }
+
+    /**
+     * Sets the sites along with their exact heights, e.g. for deterministic fixtures.
+     *
+     * Noise and shaping are skipped, each terrain vertex takes the average height of the sites
+     * generating its regions, i.e. the sites it's equidistant to.
+     */
+    pub fn set_sites_with_heights(&mut self, sites: Vec<(f64, f64, f64)>) -> &mut VoronoiTerrainBuilder {
+        self.sites = sites.iter().map(|&(x, y, _)| (x, y)).collect();
+        self.site_sampler = None;
+        self.site_heights = Some(sites.iter().map(|&(_, _, z)| z).collect());
+        self
+    }
+
+    /**
+     * Sets the distance sites keep from the bounds of the sites, as sites near the bounds produce
+     * degenerate clipped regions. Sites within the margin are dropped when building, a site
+     * sampler instead samples within its bounds inset by the margin. Negative margins are treated
+     * as zero.
+     */
+    pub fn set_boundary_margin(&mut self, boundary_margin: f64) -> &mut VoronoiTerrainBuilder {
+        self.boundary_margin = boundary_margin.max(0.0);
+        self
+    }
+
+    /**
+     * Sets a polygon clipping the terrain to an arbitrary shape.
+     *
+     * Regions whose centers fall outside of the polygon are dropped after the build, along with
+     * any terrain vertices and edges only they used.
+     */
+    pub fn set_mask_polygon(&mut self, mask_polygon: Vec<(f64, f64)>) -> &mut VoronoiTerrainBuilder {
+        self.mask_polygon = Some(mask_polygon);
+        self
+    }
+
+    pub fn set_water_level(&mut self, water_level: u32) -> &mut VoronoiTerrainBuilder {
+        self.water_level = water_level;
+        self
+    }
+
+    /** Sets the height reported by `height_at` for positions outside of the terrain. */
+    pub fn set_out_of_bounds_height(&mut self, height: Option<f64>) -> &mut VoronoiTerrainBuilder {
+        self.out_of_bounds_height = height;
+        self
+    }
+
+    pub fn set_height(&mut self, height: u32) -> &mut VoronoiTerrainBuilder {
+        self.height = height;
+        self
+    }
+
+    /**
+     * Sets how strongly the first noise pass warps the coordinates of a second pass.
+     *
+     * A second sample is taken at coordinates offset by the first sample's height times
+     * `strength`, producing plateaus and canyons. A strength of 0 disables the second pass.
+     */
+    pub fn set_height_feedback(&mut self, strength: f64) -> &mut VoronoiTerrainBuilder {
+        self.height_feedback = strength;
+        self
+    }
+
+    /**
+     * Shapes the noise into a continent-and-ocean layout centered on the sites.
+     *
+     * The noise is offset relative to the water level and falls off with distance from the center
+     * of the sites, so the rim of the terrain is ocean.
+     */
+    pub fn set_continent_layout(&mut self, layout: ContinentLayout) -> &mut VoronoiTerrainBuilder {
+        self.continent_layout = Some(layout);
+        self
+    }
+
+    /**
+     * Adds concentric bands of elevation which rise toward a center point.
+     *
+     * Each band of `band_width` further from `center` is `band_height` lower than the last,
+     * the bands are added to the noise height. A `band_width` which isn't positive removes the
+     * bands.
+     */
+    pub fn set_concentric_elevation(&mut self,
+                                    center: (f64, f64),
+                                    band_height: f64,
+                                    band_width: f64)
+                                    -> &mut VoronoiTerrainBuilder {
+        self.concentric_elevation = if band_width > 0.0 {
+            Some((center, band_height, band_width))
+        } else {
+            None
+        };
+        self
+    }
+
+    /** Sets the maximum time triangulation may take before `build` is aborted. */
+    pub fn set_build_timeout(&mut self, timeout: Duration) -> &mut VoronoiTerrainBuilder {
+        self.build_timeout = Some(timeout);
+        self
+    }
+
+    /**
+     * Sets whether each terrain vertex's edges are sorted counter-clockwise by angle around it.
+     *
+     * By default edges are in triangulation order, which isn't geometrically meaningful.
+     */
+    pub fn set_sort_vertex_edges(&mut self, sort_vertex_edges: bool) -> &mut VoronoiTerrainBuilder {
+        self.sort_vertex_edges = sort_vertex_edges;
+        self
+    }
+
+    /**
+     * Sets a function which is run over each terrain vertex, along with its index, once
+     * generated. Region centers and normals are recalculated afterwards.
+     */
+    pub fn set_vertex_post_process<F>(&mut self, post_process: F) -> &mut VoronoiTerrainBuilder
+        where F: Fn(usize, &mut TerrainVertex) + 'static
+    {
+        self.vertex_post_process = Some(Box::new(post_process));
+        self
+    }
+
+    /**
+     * Sets a function of height giving the desired number of sites per unit area.
+     *
+     * The build becomes two pass, a coarse terrain is built from the sites then each region
+     * receives extra random sites until it matches the density at its height, e.g. for finer
+     * cells in mountains. Extra sites are kept within the bounds of the original sites.
+     */
+    pub fn set_adaptive_density<F>(&mut self, density: F) -> &mut VoronoiTerrainBuilder
+        where F: Fn(f64) -> f64 + 'static
+    {
+        self.adaptive_density = Some(Box::new(density));
+        self
+    }
+
+    /**
+     * Sets whether noise is skipped, leaving a flat base terrain at the water height.
+     *
+     * Other shaping, such as concentric elevation, is still applied.
+     */
+    pub fn set_flat(&mut self, flat: bool) -> &mut VoronoiTerrainBuilder {
+        self.flat = flat;
+        self
+    }
+
+    /**
+     * Adds a control point pinning the terrain height at a position, e.g. for a peak or valley.
+     *
+     * Heights within `radius` are blended toward the control height with a smooth falloff,
+     * overlapping control points blend by their weighted average. Control points with a
+     * non-positive radius affect no heights so are ignored.
+     */
+    pub fn add_control_point(&mut self, x: f64, y: f64, height: f64, radius: f64) -> &mut VoronoiTerrainBuilder {
+        if radius > 0.0 {
+            self.control_points.push((x, y, height, radius));
+        }
+        self
+    }
+
+    pub fn build(&self) -> Result<VoronoiTerrain, TerrainBuildError> {
+        let (sites, site_heights) = self.sites();
+        let extent = site_extent(&sites);
+        self.build_from_sites(sites, site_heights, extent, self.adaptive_density())
+    }
+
+    /**
+     * Builds a single chunk of a larger world whose seams match its neighbouring chunks.
+     *
+     * The sites are treated as local to the chunk and offset into world space so that noise is
+     * sampled consistently across chunks. The continent layout is centered on the sites of chunk
+     * (0, 0) for every chunk. Adaptive density isn't supported as the densified sites of
+     * neighbouring chunks wouldn't agree along their seams, so it's ignored.
+     *
+     * @param chunk_x The x index of the chunk.
+     * @param chunk_y The y index of the chunk.
+     * @param chunk_size The width and height of each chunk.
+     */
+    pub fn build_chunk(&self,
+                       chunk_x: i32,
+                       chunk_y: i32,
+                       chunk_size: f64)
+                       -> Result<VoronoiTerrain, TerrainBuildError> {
+        let offset = (chunk_x as f64 * chunk_size, chunk_y as f64 * chunk_size);
+        let (sites, site_heights) = self.sites();
+
+        // Shape every chunk by the same extent so the layout is continuous across seams.
+        let extent = site_extent(&sites);
+        let sites = sites.into_iter()
+            .map(|(x, y)| (x + offset.0, y + offset.1))
+            .collect();
+        self.build_from_sites(sites, site_heights, extent, None)
+    }
+
+    /**
+     * Builds a coarse preview of the terrain from a subset of the sites.
+     *
+     * Sites are subsampled evenly and deterministically, and share the same noise as a full build
+     * so the preview resembles the final terrain.
+     *
+     * @param fraction The fraction of sites to keep, clamped to [0, 1].
+     */
+    pub fn build_preview(&self, fraction: f64) -> Result<VoronoiTerrain, TerrainBuildError> {
+        let fraction = fraction.clamp(0.0, 1.0);
+
+        // Keep a site each time the running total of the fraction crosses an integer.
+        let (all_sites, all_site_heights) = self.sites();
+        let kept: Vec<usize> = (0..all_sites.len())
+            .filter(|&i| ((i + 1) as f64 * fraction).floor() > (i as f64 * fraction).floor())
+            .collect();
+
+        let sites = kept.iter().map(|&i| all_sites[i]).collect();
+        let site_heights = all_site_heights
+            .as_ref()
+            .map(|site_heights| kept.iter().map(|&i| site_heights[i]).collect());
+
+        // Shape the preview as the full build would be.
+        self.build_from_sites(sites, site_heights, site_extent(&all_sites), self.adaptive_density())
+    }
+
+    /**
+     * The sites to build from and their heights, if set, without those within the boundary margin
+     * of their bounds. Sites generated by the site sampler are instead sampled within its bounds
+     * inset by the margin, and have no set heights.
+     */
+    fn sites(&self) -> (Vec<(f64, f64)>, Option<Vec<f64>>) {
+        if let Some(ref site_sampler) = self.site_sampler {
+            return (site_sampler.inset(self.boundary_margin).sample(self.seed), None);
+        }
+        if self.boundary_margin == 0.0 {
+            return (self.sites.clone(), self.site_heights.clone());
+        }
+
+        let (min, max) = point_bounds(&self.sites);
+        let kept: Vec<usize> = (0..self.sites.len())
+            .filter(|&i| {
+                let (x, y) = self.sites[i];
+                x - min.0 >= self.boundary_margin && max.0 - x >= self.boundary_margin &&
+                y - min.1 >= self.boundary_margin && max.1 - y >= self.boundary_margin
+            })
+            .collect();
+
+        let sites = kept.iter().map(|&i| self.sites[i]).collect();
+        let site_heights = self.site_heights
+            .as_ref()
+            .map(|site_heights| kept.iter().map(|&i| site_heights[i]).collect());
+        (sites, site_heights)
+    }
+
+    /** The adaptive density function, if any. */
+    fn adaptive_density(&self) -> Option<&dyn Fn(f64) -> f64> {
+        self.adaptive_density.as_deref()
+    }
+
+    /**
+     * Builds the terrain from a set of sites.
+     *
+     * @param extent The center of the sites and the distance from it to the corners of their
+     *               bounds, which the continent layout is shaped by.
+     * @param adaptive_density The desired number of sites per unit area at a height, if any.
+     */
+    fn build_from_sites(&self,
+                        sites: Vec<(f64, f64)>,
+                        site_heights: Option<Vec<f64>>,
+                        extent: ((f64, f64), f64),
+                        adaptive_density: Option<&dyn Fn(f64) -> f64>)
+                        -> Result<VoronoiTerrain, TerrainBuildError> {
+        let noise = Fbm::new().set_seed(self.seed);
+
+        let deadline = self.build_timeout.map(|timeout| Instant::now() + timeout);
+
+        // Precomputed heights belong to the given sites, so those can't be densified.
+        let sites = match (adaptive_density, &site_heights) {
+            (Some(density), &None) => self.densify_sites(sites, density, &noise, extent, deadline)?,
+            _ => sites,
+        };
+
+        let (mut terrain_graph, mut region_graph) = match site_heights {
+            Some(_) => triangulate(&sites, |_, _| 0.0, deadline)?,
+            None => triangulate(&sites, |x, y| self.sample_height(&noise, extent, x, y), deadline)?,
+        };
+
+        if let Some(ref site_heights) = site_heights {
+            // Regions are indexed by site, so a vertex's regions are the sites generating it.
+            let mut sums: Vec<(f64, usize)> = vec![(0.0, 0); terrain_graph.vertices.len()];
+            for (region_index, region) in region_graph.vertices.iter().enumerate() {
+                for &i in region.vertices.iter() {
+                    sums[i].0 += site_heights[region_index];
+                    sums[i].1 += 1;
+                }
+            }
+            for (vertex, &(sum, count)) in terrain_graph.vertices.iter_mut().zip(sums.iter()) {
+                if count > 0 {
+                    vertex.position.z = sum / count as f64;
+                }
+            }
+            update_geometry(&mut terrain_graph, &mut region_graph);
+        }
+
+        if self.sort_vertex_edges {
+            sort_edges_by_angle(&mut terrain_graph);
+        }
+
+        if let Some(ref post_process) = self.vertex_post_process {
+            for (i, vertex) in terrain_graph.vertices.iter_mut().enumerate() {
+                post_process(i, vertex);
+            }
+            update_geometry(&mut terrain_graph, &mut region_graph);
+        }
+
+        let mut sites = sites;
+        if let Some(ref mask_polygon) = self.mask_polygon {
+            let keep: Vec<bool> = region_graph.vertices
+                .iter()
+                .map(|region| point_in_polygon((region.center.x, region.center.y), mask_polygon))
+                .collect();
+            let (masked_terrain_graph, masked_region_graph) =
+                retain_regions(&terrain_graph, &region_graph, &keep);
+            terrain_graph = masked_terrain_graph;
+            region_graph = masked_region_graph;
+            sites = sites.into_iter()
+                .zip(keep)
+                .filter(|&(_, keep)| keep)
+                .map(|(site, _)| site)
+                .collect();
+            update_geometry(&mut terrain_graph, &mut region_graph);
+        }
+
+        Ok(VoronoiTerrain {
+            terrain_graph: terrain_graph,
+            region_graph: region_graph,
+            site_of_region: sites,
+            water_level: self.water_level,
+            height: self.height,
+            out_of_bounds_height: self.out_of_bounds_height,
+            region_adjacency: OnceLock::new(),
+            regions_by_vertex: OnceLock::new(),
+        })
+    }
+
+    /**
+     * Adds sites to a coarse terrain built from the given sites to match a density by height.
+     *
+     * @param density The desired number of sites per unit area at a height.
+     * @return The given sites followed by the added sites.
+     */
+    fn densify_sites(&self,
+                     sites: Vec<(f64, f64)>,
+                     density: &dyn Fn(f64) -> f64,
+                     noise: &Fbm<f64>,
+                     extent: ((f64, f64), f64),
+                     deadline: Option<Instant>)
+                     -> Result<Vec<(f64, f64)>, TerrainBuildError> {
+        let (terrain_graph, region_graph) =
+            triangulate(&sites, |x, y| self.sample_height(noise, extent, x, y), deadline)?;
+
+        let (min, max) = point_bounds(&sites);
+
+        let seed = self.seed as u64;
+        let mut rng = XorShiftRng::from_seed([seed as u32, (seed >> 32) as u32, 0x9e37_79b9, 0x7f4a_7c15]);
+
+        let mut densified = sites.clone();
+        for region in region_graph.vertices.iter() {
+            if deadline.is_some_and(|deadline| Instant::now() > deadline) {
+                return Err(TerrainBuildError::Timeout);
+            }
+
+            // Hull regions reach far beyond the sites, only the area within their bounds is densified.
+            let polygon: Vec<(f64, f64)> = region.vertices.iter()
+                .map(|&i| (terrain_graph.vertices[i].position.x, terrain_graph.vertices[i].position.y))
+                .collect();
+            let polygon = clip_polygon_to_bounds(&polygon, (min, max));
+            let triangles: Vec<[usize; 3]> = fan_triangles(&(0..polygon.len()).collect::<Vec<usize>>());
+            let areas: Vec<f64> = triangles.iter()
+                .map(|t| polygon_area(&[polygon[t[0]], polygon[t[1]], polygon[t[2]]]))
+                .collect();
+            let area = areas.iter().sum::<f64>();
+            if triangles.is_empty() {
+                continue;
+            }
+
+            // The region's own site counts toward its density.
+            let extra = (density(region.center.z) * area).round() as usize;
+            for _ in 1..extra {
+                // Pick a triangle weighted by area, then a uniform point within it.
+                let mut pick = rng.gen::<f64>() * area;
+                let triangle = triangles.iter()
+                    .zip(areas.iter())
+                    .find(|&(_, &triangle_area)| {
+                        pick -= triangle_area;
+                        pick <= 0.0
+                    })
+                    .map_or(triangles[triangles.len() - 1], |(triangle, _)| *triangle);
+
+                let (mut u, mut v) = (rng.gen::<f64>(), rng.gen::<f64>());
+                if u + v > 1.0 {
+                    u = 1.0 - u;
+                    v = 1.0 - v;
+                }
+                let (a, b, c) = (polygon[triangle[0]], polygon[triangle[1]], polygon[triangle[2]]);
+                densified.push((a.0 + u * (b.0 - a.0) + v * (c.0 - a.0), a.1 + u * (b.1 - a.1) + v * (c.1 - a.1)));
+            }
+        }
+        Ok(densified)
+    }
+
+    /**
+     * Samples the terrain height at a position.
+     *
+     * @param extent The center of the sites and the distance from it to the corners of their bounds.
+     */
+    fn sample_height(&self, noise: &Fbm<f64>, extent: ((f64, f64), f64), x: f64, y: f64) -> f64 {
+        let mut height = self.sample_noise(noise, x, y);
+
+        // Flat terrain ignores the layout, as it does the noise.
+        if let (Some(layout), false) = (self.continent_layout, self.flat) {
+            let (center, radius) = extent;
+            let distance = if radius > 0.0 { distance2((x, y), center).sqrt() / radius } else { 0.0 };
+            let (offset, falloff) = layout.shape();
+
+            // Noise is centered on zero, recenter it on the water level before shaping.
+            let water_height = water_height(self.water_level, self.height);
+            height = (height + water_height + offset - falloff * distance * distance)
+                .clamp(NOISE_RANGE.0, NOISE_RANGE.1);
+        }
+
+        if let Some((center, band_height, band_width)) = self.concentric_elevation {
+            let band = (distance2((x, y), center).sqrt() / band_width).floor();
+            height -= band * band_height;
+        }
+
+        let mut total_weight = 0.0;
+        let mut max_weight: f64 = 0.0;
+        let mut weighted_height = 0.0;
+        for &(control_x, control_y, control_height, radius) in self.control_points.iter() {
+            let t = 1.0 - distance2((x, y), (control_x, control_y)).sqrt() / radius;
+            if t <= 0.0 {
+                continue;
+            }
+
+            // Smoothstep falloff from 1 at the center to 0 at the radius.
+            let weight = t * t * (3.0 - 2.0 * t);
+            total_weight += weight;
+            max_weight = max_weight.max(weight);
+            weighted_height += weight * control_height;
+        }
+        if total_weight > 0.0 {
+            height += (weighted_height / total_weight - height) * max_weight;
+        }
+
+        height
+    }
+
+    /** Samples the noise height at a position, normalized to `NOISE_RANGE`. */
+    fn sample_noise(&self, noise: &Fbm<f64>, x: f64, y: f64) -> f64 {
+        if self.flat {
+            return water_height(self.water_level, self.height);
+        }
+
+        let height = normalize_fbm(noise.get([x, y]));
+        if self.height_feedback == 0.0 {
+            return height;
+        }
+
+        // The first pass is normalized so a single feedback pass keeps the offset bounded.
+        let offset = height * self.height_feedback;
+        normalize_fbm(noise.get([x + offset, y + offset]))
+    }
+}
+
+/**
+ * Exports the Voronoi regions of a triangulation in a deterministic order.
+ *
+ * The triangulation stores its triangles in a hash map so the order of the vertices it exports,
+ * and where each region's boundary starts, differ between runs. Vertices are sorted by position
+ * instead, dropping those bounding no region, and each boundary starts at its lowest index.
+ *
+ * @return The vertices and the indices of the vertices bounding each region, indexed by site.
+ */
+fn voronoi_regions(dt: &Delaunay2D) -> (Vec<(f64, f64)>, Vec<Vec<usize>>) {
+    let (vertices, cells) = dt.export_voronoi_regions();
+
+    let mut regions_by_vertex: Vec<Vec<usize>> = vec![Vec::new(); vertices.len()];
+    for (region_index, cell) in cells.iter().enumerate() {
+        for &i in cell.iter() {
+            regions_by_vertex[i].push(region_index);
+        }
+    }
+
+    // Vertices of triangles between the frame's corners alone bound no region. Coincident
+    // vertices are ordered by the regions they bound.
+    let mut order: Vec<usize> = (0..vertices.len()).filter(|&i| !regions_by_vertex[i].is_empty()).collect();
+    order.sort_by(|&a, &b| {
+        vertices[a].0.total_cmp(&vertices[b].0)
+            .then(vertices[a].1.total_cmp(&vertices[b].1))
+            .then_with(|| regions_by_vertex[a].cmp(&regions_by_vertex[b]))
+    });
+
+    let mut sorted_index: Vec<usize> = vec![0; vertices.len()];
+    for (sorted, &i) in order.iter().enumerate() {
+        sorted_index[i] = sorted;
+    }
+
+    let cells = cells.into_iter()
+        .map(|cell| {
+            let mut cell: Vec<usize> = cell.into_iter().map(|i| sorted_index[i]).collect();
+            let start = (0..cell.len()).min_by_key(|&k| cell[k]).unwrap_or(0);
+            cell.rotate_left(start);
+            cell
+        })
+        .collect();
+    (order.into_iter().map(|i| vertices[i]).collect(), cells)
+}
+
+/**
+ * Builds the terrain and region graphs from the voronoi diagram of a set of sites.
+ *
+ * @param sites The sites generating each region.
+ * @param height Calculates the height of the terrain at a position.
+ * @param deadline The time after which triangulation is aborted, if any.
+ */
+fn triangulate<F>(sites: &[(f64, f64)],
+                  height: F,
+                  deadline: Option<Instant>)
+                  -> Result<(Graph<TerrainVertex>, Graph<Region>), TerrainBuildError>
+    where F: Fn(f64, f64) -> f64
+{
+    // TODO(orglofch): Calculate the boundary from the sites.
+    // TODO(orglofch): Port to Fortunes algorithm.
+    let mut dt = Delaunay2D::new((0.0, 0.0), 9999.0);
+
+    for site in sites.iter() {
+        if deadline.is_some_and(|deadline| Instant::now() > deadline) {
+            return Err(TerrainBuildError::Timeout);
+        }
+        dt.add_point(*site);
+    }
+
+    let (dt_vertices, dt_cells) = voronoi_regions(&dt);
+
+    // Generate the set of connecting edges for each vertex and the reverse for fast lookup.
+    let mut terrain_edges_by_vertex_index: HashMap<usize, Vec<usize>> = HashMap::with_capacity(dt_vertices.len());
+
+    // Capacity is a rough estimate of the total number of edges in the graph.
+    // The average number of edges in a voronoi cell is < 6 and each is shared between 2 cells.
+    let mut terrain_edges: Vec<(usize, usize)> = Vec::with_capacity(dt_vertices.len() * 2);
+    let mut region_edges: Vec<(usize, usize)> = Vec::with_capacity(dt_vertices.len() * 2);
+
+    // Terrain edges are near 1:1 with region edges (all except for the exterior cells).
+    // TODO(orglofc): Consider combining the edges to a common structure so one can transition between the two.
+    let mut region_by_terrain_edge: HashMap<(usize, usize), usize> = HashMap::with_capacity(dt_vertices.len() * 2);
+
+    // For fast check retrieval of existing edges.
+    let mut terrain_edge_index_by_edge: HashMap<(usize, usize), usize> = HashMap::with_capacity(dt_vertices.len() * 2);
+
+    for (region_index, dt_cell) in dt_cells.iter().enumerate() {
+        for (vertex_index, current_vertex_index) in dt_cell.iter().enumerate() {
+            let next_vertex_index = dt_cell[(vertex_index + 1) % dt_cell.len()];
+
+            // If the reverse edge already exists then we can skip adding this as a new edge.
+            // The winding order will guarantee the duplicate edge is the reverse of the current edge.
+            // We can also use the knowledge of when duplicates occur to mark region edges.
+            let reverse_edge = (next_vertex_index, *current_vertex_index);
+
+            let edge_index = match terrain_edge_index_by_edge.get(&reverse_edge) {
+                Some(&edge_index) => {
+                    // Insert a new region edge.
+                    let other_region = region_by_terrain_edge.get(&reverse_edge)
+                        .expect("The reverse index should exist for the region index");
+                    region_edges.push((region_index, *other_region));
+                    edge_index
+                },
+                None => {
+                    // Insert the new terrain edge.
+                    let edge = (*current_vertex_index, next_vertex_index);
+                    region_by_terrain_edge.insert(edge, region_index);
+                    terrain_edge_index_by_edge.insert(edge, terrain_edges.len());
+                    terrain_edges.push(edge);
+                    terrain_edges.len() - 1
+                }
+            };
+
+            terrain_edges_by_vertex_index.entry(*current_vertex_index)
+                .or_insert(Vec::with_capacity(5)) // Average edges < 6.
+                .push(edge_index);
+        }
+    }
+
+    // Create terrain vertices.
+    let mut terrain_vertices: Vec<TerrainVertex> = Vec::with_capacity(dt_vertices.len());
+    for (i, vertex) in dt_vertices.into_iter().enumerate() {
+        let height = height(vertex.0, vertex.1);
+        let terrain_vertex = TerrainVertex {
+        position: Point3::new(vertex.0, vertex.1, height),
+            normal: Vector3::zero(),
+            color: [1.0, 1.0, 1.0, 1.0],
+            edges: terrain_edges_by_vertex_index.remove(&i).unwrap(),
+        };
+        terrain_vertices.push(terrain_vertex);
+    }
+
+    // Create regions.
+    let mut regions: Vec<Region> = Vec::with_capacity(dt_cells.len());
+
+    for dt_cell in dt_cells.iter() {
+        let region = Region {
+            center: region_center(&terrain_vertices, dt_cell),
+            normal: region_normal(&terrain_vertices, dt_cell),
+            vertices: dt_cell.clone(),
+            edges: Vec::new(), // TODO(orglofch):
+        };
+        regions.push(region);
+    }
+
+    update_vertex_normals(&mut terrain_vertices, &regions);
+
+    let terrain_graph = Graph {
+        vertices: terrain_vertices,
+        edges: terrain_edges,
+    };
+
+    let region_graph = Graph {
+        vertices: regions,
+        edges: region_edges,
+    };
+
+    Ok((terrain_graph, region_graph))
+}
+
+/** Finds the neighbouring cells of each voronoi cell, those sharing an edge. */
+fn voronoi_adjacency(cells: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    let mut cell_by_edge: HashMap<(usize, usize), usize> = HashMap::new();
+    let mut adjacency: Vec<Vec<usize>> = vec![Vec::new(); cells.len()];
+    for (cell_index, cell) in cells.iter().enumerate() {
+        for i in 0..cell.len() {
+            let edge = (cell[i], cell[(i + 1) % cell.len()]);
+            // The winding order guarantees a shared edge appears reversed in the other cell.
+            match cell_by_edge.get(&(edge.1, edge.0)) {
+                Some(&other) => {
+                    adjacency[cell_index].push(other);
+                    adjacency[other].push(cell_index);
+                },
+                None => {
+                    cell_by_edge.insert(edge, cell_index);
+                },
+            }
+        }
+    }
+    adjacency
+}
+
+/** Triangulates a polygon as a fan from its first vertex. */
+fn fan_triangles(polygon: &[usize]) -> Vec<[usize; 3]> {
+    (1..polygon.len().saturating_sub(1))
+        .map(|i| [polygon[0], polygon[i], polygon[i + 1]])
+        .collect()
+}
+
+/** Sorts the edges of each terrain vertex counter-clockwise by their angle around the vertex. */
+fn sort_edges_by_angle(graph: &mut Graph<TerrainVertex>) {
+    let positions: Vec<Point3<f64>> = graph.vertices.iter().map(|vertex| vertex.position).collect();
+    let edges = &graph.edges;
+
+    for (vertex_index, vertex) in graph.vertices.iter_mut().enumerate() {
+        let angle = |edge_index: usize| {
+            let (a, b) = edges[edge_index];
+            let other = if a == vertex_index { b } else { a };
+            let offset = positions[other] - positions[vertex_index];
+            offset.y.atan2(offset.x)
+        };
+        vertex.edges.sort_by(|&a, &b| angle(a).partial_cmp(&angle(b)).unwrap());
+    }
+}
+
+/**
+ * Recalculates the centers and normals of all regions and vertices after their heights change.
+ */
+fn update_geometry(terrain_graph: &mut Graph<TerrainVertex>, region_graph: &mut Graph<Region>) {
+    for region in region_graph.vertices.iter_mut() {
+        region.center = region_center(&terrain_graph.vertices, &region.vertices);
+        region.normal = region_normal(&terrain_graph.vertices, &region.vertices);
+    }
+    update_vertex_normals(&mut terrain_graph.vertices, &region_graph.vertices);
+}
+
+/** Calculates the center of a region as the average of its boundary vertices. */
+fn region_center(vertices: &[TerrainVertex], polygon: &[usize]) -> Point3<f64> {
+    let sum = polygon.iter().fold(Vector3::zero(), |sum, &i| sum + vertices[i].position.to_vec());
+    Point3::from_vec(sum / polygon.len() as f64)
+}
+
+/** Calculates the upward facing normal of a region from 3 of its boundary vertices. */
+fn region_normal(vertices: &[TerrainVertex], polygon: &[usize]) -> Vector3<f64> {
+    let p0 = vertices[polygon[0]].position;
+    let e1 = vertices[polygon[1]].position - p0;
+    let e2 = vertices[polygon[2]].position - p0;
+    let normal = e1.cross(e2).normalize();
+
+    // The winding order of the cell isn't guaranteed so orient the normal upwards.
+    if normal.z < 0.0 { -normal } else { normal }
+}
+
+/** Sets the normal of each vertex to the average normal of the regions it bounds. */
+fn update_vertex_normals(vertices: &mut [TerrainVertex], regions: &[Region]) {
+    for vertex in vertices.iter_mut() {
+        vertex.normal = Vector3::zero();
+    }
+    for region in regions.iter() {
+        for &i in region.vertices.iter() {
+            vertices[i].normal += region.normal;
+        }
+    }
+    for vertex in vertices.iter_mut() {
+        if vertex.normal != Vector3::zero() {
+            vertex.normal = vertex.normal.normalize();
+        }
+    }
+}
+
+/** Maps a water level from [0, height] into `NOISE_RANGE`. */
+fn water_height(water_level: u32, height: u32) -> f64 {
+    NOISE_RANGE.0 + (NOISE_RANGE.1 - NOISE_RANGE.0) * water_level as f64 / height as f64
+}
+
+/** The number of octaves summed by the default Fbm which heights are sampled from. */
+const FBM_OCTAVES: i32 = 6;
+
+/** The amplitude of each octave of the default Fbm relative to the previous octave. */
+const FBM_PERSISTENCE: f64 = 0.5;
+
+/**
+ * Copies the graphs keeping only a subset of the regions, renumbering everything that remains.
+ *
+ * Terrain vertices and edges are kept only if they bound a kept region.
+ *
+ * @param keep Whether to keep each region, indexed by region.
+ */
+fn retain_regions(terrain_graph: &Graph<TerrainVertex>,
+                  region_graph: &Graph<Region>,
+                  keep: &[bool])
+                  -> (Graph<TerrainVertex>, Graph<Region>) {
+    let mut region_index_map: Vec<Option<usize>> = vec![None; region_graph.vertices.len()];
+    let mut vertex_index_map: Vec<Option<usize>> = vec![None; terrain_graph.vertices.len()];
+    let mut kept_vertices: Vec<usize> = Vec::new();
+    let mut kept_edges: Vec<(usize, usize)> = Vec::new();
+
+    let mut regions: Vec<Region> = Vec::new();
+    for (region_index, region) in region_graph.vertices.iter().enumerate() {
+        if !keep[region_index] {
+            continue;
+        }
+        region_index_map[region_index] = Some(regions.len());
+
+        let polygon: Vec<usize> = region.vertices.iter()
+            .map(|&i| {
+                *vertex_index_map[i].get_or_insert_with(|| {
+                    kept_vertices.push(i);
+                    kept_vertices.len() - 1
+                })
+            })
+            .collect();
+        for i in 0..polygon.len() {
+            let (a, b) = (polygon[i], polygon[(i + 1) % polygon.len()]);
+            kept_edges.push(if a < b { (a, b) } else { (b, a) });
+        }
+
+        regions.push(Region {
+            center: region.center,
+            normal: region.normal,
+            vertices: polygon,
+            edges: Vec::new(),
+        });
+    }
+    kept_edges.sort();
+    kept_edges.dedup();
+
+    let mut vertices: Vec<TerrainVertex> = kept_vertices.iter()
+        .map(|&i| {
+            TerrainVertex {
+                position: terrain_graph.vertices[i].position,
+                normal: terrain_graph.vertices[i].normal,
+                color: terrain_graph.vertices[i].color,
+                edges: Vec::new(),
+            }
+        })
+        .collect();
+    for (edge_index, &(a, b)) in kept_edges.iter().enumerate() {
+        vertices[a].edges.push(edge_index);
+        vertices[b].edges.push(edge_index);
+    }
+
+    let region_edges = region_graph.edges
+        .iter()
+        .filter_map(|&(a, b)| match (region_index_map[a], region_index_map[b]) {
+            (Some(a), Some(b)) => Some((a, b)),
+            _ => None,
+        })
+        .collect();
+
+    (Graph {
+         vertices: vertices,
+         edges: kept_edges,
+     },
+     Graph {
+         vertices: regions,
+         edges: region_edges,
+     })
+}
+
+/**
+ * Normalizes Fbm noise to `NOISE_RANGE`.
+ *
+ * Fbm sums its octaves, each scaled by the persistence to the power of the octave, so the sum is
+ * divided by the total amplitude. The rare peaks left overshooting [-1, 1] are clamped.
+ */
+fn normalize_fbm(height: f64) -> f64 {
+    let amplitude: f64 = (0..FBM_OCTAVES).map(|octave| FBM_PERSISTENCE.powi(octave)).sum();
+    (height / amplitude).clamp(NOISE_RANGE.0, NOISE_RANGE.1)
+}
+
+/** The slope, rise over planar run, between two points. Vertical edges are infinitely steep. */
+fn edge_slope(a: Point3<f64>, b: Point3<f64>) -> f64 {
+    let run = distance2((a.x, a.y), (b.x, b.y)).sqrt();
+    if run == 0.0 {
+        return if a.z == b.z { 0.0 } else { f64::INFINITY };
+    }
+    (b.z - a.z).abs() / run
+}
+
+/** The minimum and maximum corners of the bounds of a set of 2D points. */
+fn point_bounds(points: &[(f64, f64)]) -> ((f64, f64), (f64, f64)) {
+    points.iter().fold(
+        ((f64::INFINITY, f64::INFINITY), (f64::NEG_INFINITY, f64::NEG_INFINITY)),
+        |(min, max), &point| ((min.0.min(point.0), min.1.min(point.1)), (max.0.max(point.0), max.1.max(point.1))))
+}
+
+/**
+ * Generates Poisson disk distributed points within bounds using Bridson's algorithm.
+ *
+ * A maximal Poisson disk sampling with minimum distance r has around 0.7 / r^2 points per unit
+ * area, the minimum distance is chosen from this to match the density.
+ */
+fn poisson_disk_sites<R: Rng>(rng: &mut R,
+                              bounds: ((f64, f64), (f64, f64)),
+                              density: f64)
+                              -> Vec<(f64, f64)> {
+    // The number of candidates tried around each active point before it's retired.
+    let attempts = 30;
+
+    let (min, max) = bounds;
+    if density <= 0.0 || max.0 <= min.0 || max.1 <= min.1 {
+        return Vec::new();
+    }
+    let radius = (0.7 / density).sqrt();
+
+    // Cells are small enough to hold at most one point.
+    let cell_size = radius / 2.0f64.sqrt();
+    let columns = ((max.0 - min.0) / cell_size).ceil() as usize;
+    let rows = ((max.1 - min.1) / cell_size).ceil() as usize;
+    let cell_of = |point: (f64, f64)| {
+        (((point.0 - min.0) / cell_size) as usize).min(columns - 1) +
+        (((point.1 - min.1) / cell_size) as usize).min(rows - 1) * columns
+    };
+    let mut grid: Vec<Option<usize>> = vec![None; columns * rows];
+
+    let first = (min.0 + rng.gen::<f64>() * (max.0 - min.0), min.1 + rng.gen::<f64>() * (max.1 - min.1));
+    let mut points = vec![first];
+    let mut active = vec![0];
+    grid[cell_of(first)] = Some(0);
+
+    while !active.is_empty() {
+        let active_index = (rng.gen::<f64>() * active.len() as f64) as usize % active.len();
+        let center = points[active[active_index]];
+
+        let mut found = false;
+        for _ in 0..attempts {
+            // A candidate in the annulus between the radius and twice the radius.
+            let angle = rng.gen::<f64>() * 2.0 * std::f64::consts::PI;
+            let distance = radius * (1.0 + rng.gen::<f64>());
+            let candidate = (center.0 + distance * angle.cos(), center.1 + distance * angle.sin());
+            if candidate.0 < min.0 || candidate.0 > max.0 || candidate.1 < min.1 || candidate.1 > max.1 {
+                continue;
+            }
+
+            let cell = cell_of(candidate);
+            let (column, row) = ((cell % columns) as i64, (cell / columns) as i64);
+            let mut too_close = false;
+            for neighbour_row in (row - 2).max(0)..(row + 3).min(rows as i64) {
+                for neighbour_column in (column - 2).max(0)..(column + 3).min(columns as i64) {
+                    if let Some(other) = grid[neighbour_column as usize + neighbour_row as usize * columns] {
+                        too_close |= distance2(points[other], candidate) < radius * radius;
+                    }
+                }
+            }
+            if too_close {
+                continue;
+            }
+
+            grid[cell] = Some(points.len());
+            active.push(points.len());
+            points.push(candidate);
+            found = true;
+            break;
+        }
+
+        if !found {
+            active.swap_remove(active_index);
+        }
+    }
+    points
+}
+
+/** Squared distance between two 2D points. */
+fn distance2(a: (f64, f64), b: (f64, f64)) -> f64 {
+    let dx = a.0 - b.0;
+    let dy = a.1 - b.1;
+    dx * dx + dy * dy
+}
+
+/** The squared distance from a point to the segment between a and b. */
+fn segment_distance2(point: (f64, f64), a: (f64, f64), b: (f64, f64)) -> f64 {
+    let length2 = distance2(a, b);
+    if length2 == 0.0 {
+        return distance2(point, a);
+    }
+
+    let t = (((point.0 - a.0) * (b.0 - a.0) + (point.1 - a.1) * (b.1 - a.1)) / length2).clamp(0.0, 1.0);
+    distance2(point, (a.0 + t * (b.0 - a.0), a.1 + t * (b.1 - a.1)))
+}
+
+/** A graph node and the cost of reaching it, ordered so the cheapest is popped first. */
+#[derive(PartialEq)]
+struct MinCost(f64, usize);
+
+impl Eq for MinCost {}
+
+impl Ord for MinCost {
+    fn cmp(&self, other: &MinCost) -> Ordering {
+        other.0.partial_cmp(&self.0).unwrap_or(Ordering::Equal)
+    }
+}
+
+impl PartialOrd for MinCost {
+    fn partial_cmp(&self, other: &MinCost) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/**
+ * Finds the shortest paths from a set of sources to every node of a graph.
+ *
+ * @param sources The source nodes and their initial costs.
+ * @param adjacency The neighbouring nodes of each node.
+ * @param cost The cost of travelling between two neighbouring nodes.
+ * @return The cost of reaching each node, infinite if unreachable, and the previous node on
+ *         each shortest path.
+ */
+fn dijkstra<F>(sources: &[(usize, f64)],
+               adjacency: &[Vec<usize>],
+               cost: F)
+               -> (Vec<f64>, Vec<Option<usize>>)
+    where F: Fn(usize, usize) -> f64
+{
+    let mut costs = vec![f64::INFINITY; adjacency.len()];
+    let mut previous = vec![None; adjacency.len()];
+    let mut heap = BinaryHeap::new();
+
+    for &(source, source_cost) in sources.iter() {
+        if source_cost < costs[source] {
+            costs[source] = source_cost;
+            heap.push(MinCost(source_cost, source));
+        }
+    }
+
+    while let Some(MinCost(node_cost, node)) = heap.pop() {
+        // Skip stale entries which have since been reached more cheaply.
+        if node_cost > costs[node] {
+            continue;
+        }
+
+        for &neighbour in adjacency[node].iter() {
+            let neighbour_cost = node_cost + cost(node, neighbour);
+            if neighbour_cost < costs[neighbour] {
+                costs[neighbour] = neighbour_cost;
+                previous[neighbour] = Some(node);
+                heap.push(MinCost(neighbour_cost, neighbour));
+            }
+        }
+    }
+
+    (costs, previous)
+}
+
+/**
+ * Clips a polygon against a horizontal plane, interpolating new vertices along crossing edges.
+ *
+ * @param above Whether to keep the part of the polygon on or above the plane, otherwise below it.
+ */
+fn clip_polygon_by_height(polygon: &[Point3<f64>], height: f64, above: bool) -> Vec<Point3<f64>> {
+    let inside = |p: &Point3<f64>| if above { p.z >= height } else { p.z < height };
+
+    let mut clipped: Vec<Point3<f64>> = Vec::with_capacity(polygon.len() + 1);
+    for i in 0..polygon.len() {
+        let a = polygon[i];
+        let b = polygon[(i + 1) % polygon.len()];
+        if inside(&a) {
+            clipped.push(a);
+        }
+        if inside(&a) != inside(&b) {
+            let t = (height - a.z) / (b.z - a.z);
+            clipped.push(a + (b - a) * t);
+        }
+    }
+    clipped
+}
+
+/**
+ * Follows the previous nodes of a shortest path tree back from a node to its source.
+ *
+ * @return The nodes along the path from the source to `to` inclusive.
+ */
+fn trace_path(previous: &[Option<usize>], to: usize) -> Vec<usize> {
+    let mut path = vec![to];
+    while let Some(node) = previous[*path.last().unwrap()] {
+        path.push(node);
+    }
+    path.reverse();
+    path
+}
+
+/** The unsigned area of a polygon, using the shoelace formula. */
+fn polygon_area(polygon: &[(f64, f64)]) -> f64 {
+    let mut area = 0.0;
+    for i in 0..polygon.len() {
+        let a = polygon[i];
+        let b = polygon[(i + 1) % polygon.len()];
+        area += a.0 * b.1 - b.0 * a.1;
+    }
+    (area * 0.5).abs()
+}
+
+/**
+ * Clips a polygon to rectangular bounds using Sutherland-Hodgman clipping.
+ *
+ * @param bounds The minimum and maximum corners of the bounds.
+ * @return The clipped polygon, empty if the polygon lies outside of the bounds.
+ */
+fn clip_polygon_to_bounds(polygon: &[(f64, f64)], bounds: ((f64, f64), (f64, f64))) -> Vec<(f64, f64)> {
+    let (min, max) = bounds;
+
+    // The distance of a point inside of each edge of the bounds, negative if outside.
+    let edges: [&dyn Fn((f64, f64)) -> f64; 4] = [&|p| p.0 - min.0,
+                                                  &|p| max.0 - p.0,
+                                                  &|p| p.1 - min.1,
+                                                  &|p| max.1 - p.1];
+
+    let mut clipped = polygon.to_vec();
+    for inside in edges.iter() {
+        let input = clipped;
+        clipped = Vec::with_capacity(input.len() + 1);
+        for i in 0..input.len() {
+            let (a, b) = (input[i], input[(i + 1) % input.len()]);
+            let (da, db) = (inside(a), inside(b));
+            if da >= 0.0 {
+                clipped.push(a);
+            }
+            if (da >= 0.0) != (db >= 0.0) {
+                let t = da / (da - db);
+                clipped.push((a.0 + t * (b.0 - a.0), a.1 + t * (b.1 - a.1)));
+            }
+        }
+    }
+    clipped
+}
+
+/** Lists the regions bounded by each terrain vertex. */
+fn regions_by_vertex(terrain_graph: &Graph<TerrainVertex>, region_graph: &Graph<Region>) -> Vec<Vec<usize>> {
+    let mut regions_by_vertex: Vec<Vec<usize>> = vec![Vec::new(); terrain_graph.vertices.len()];
+    for (region_index, region) in region_graph.vertices.iter().enumerate() {
+        for &vertex_index in region.vertices.iter() {
+            regions_by_vertex[vertex_index].push(region_index);
+        }
+    }
+    regions_by_vertex
+}
+
+/** Finds the root of an element in a union-find forest, compressing the path along the way. */
+fn find_root(parent: &mut [usize], i: usize) -> usize {
+    let mut root = i;
+    while parent[root] != root {
+        root = parent[root];
+    }
+
+    let mut current = i;
+    while parent[current] != root {
+        let next = parent[current];
+        parent[current] = root;
+        current = next;
+    }
+    root
+}
+
+/** The area weighted centroid of a polygon, or its vertex average if it has no area. */
+fn polygon_centroid(polygon: &[(f64, f64)]) -> (f64, f64) {
+    let mut area = 0.0;
+    let mut centroid = (0.0, 0.0);
+    for i in 0..polygon.len() {
+        let a = polygon[i];
+        let b = polygon[(i + 1) % polygon.len()];
+        let cross = a.0 * b.1 - b.0 * a.1;
+        area += cross;
+        centroid.0 += (a.0 + b.0) * cross;
+        centroid.1 += (a.1 + b.1) * cross;
+    }
+
+    if area == 0.0 {
+        let sum = polygon.iter().fold((0.0, 0.0), |sum, p| (sum.0 + p.0, sum.1 + p.1));
+        return (sum.0 / polygon.len() as f64, sum.1 / polygon.len() as f64);
+    }
+    (centroid.0 / (3.0 * area), centroid.1 / (3.0 * area))
+}
+
+/** Whether a point lies inside a polygon, using the even-odd rule. */
+fn point_in_polygon(point: (f64, f64), polygon: &[(f64, f64)]) -> bool {
+    let mut inside = false;
+    for i in 0..polygon.len() {
+        let a = polygon[i];
+        let b = polygon[(i + 1) % polygon.len()];
+        if (a.1 > point.1) != (b.1 > point.1) &&
+           point.0 < (b.0 - a.0) * (point.1 - a.1) / (b.1 - a.1) + a.0 {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+/**
+ * Calculates the barycentric weights of a point within a triangle.
+ *
+ * @return The weights of a, b and c or `None` if the point lies outside the triangle.
+ */
+fn barycentric(p: (f64, f64), a: (f64, f64), b: (f64, f64), c: (f64, f64)) -> Option<(f64, f64, f64)> {
+    let det = (b.1 - c.1) * (a.0 - c.0) + (c.0 - b.0) * (a.1 - c.1);
+    if det == 0.0 {
+        return None;
+    }
+
+    let wa = ((b.1 - c.1) * (p.0 - c.0) + (c.0 - b.0) * (p.1 - c.1)) / det;
+    let wb = ((c.1 - a.1) * (p.0 - c.0) + (a.0 - c.0) * (p.1 - c.1)) / det;
+    let wc = 1.0 - wa - wb;
+
+    // Allow for a small amount of error along the triangle edges.
+    let epsilon = -1e-9;
+    if wa < epsilon || wb < epsilon || wc < epsilon {
+        return None;
+    }
+    Some((wa, wb, wc))
+}
+
+pub struct TerrainVertex {
+    pub position: Point3<f64>,
+    pub normal: Vector3<f64>,
+
+    // RGBA, white unless colorized.
+    pub color: [f32; 4],
+
+    // Indices into the set of terrain_edges.
+    pub edges: Vec<usize>,
+}
+
+pub struct Region {
+    pub center: Point3<f64>,
+    pub normal: Vector3<f64>,
+
+    // Indices into the set of terrain vertices bounding the region, in winding order.
+    pub vertices: Vec<usize>,
+
+    // Indices into the set of region_edges.
+    pub edges: Vec<usize>,
+}
+
+/** The reasons building a terrain can fail. */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TerrainBuildError {
+    // Triangulation exceeded the builder's timeout.
+    Timeout,
+}
+
+/** A ray in terrain space. */
+#[derive(Clone, Copy, Debug)]
+pub struct Ray {
+    pub origin: Point3<f64>,
+    pub direction: Vector3<f64>,
+}
+
+/** The volume visible to a camera, bounded by six inward facing planes. */
+pub struct Frustum {
+    // The left, right, bottom, top, near and far planes as (a, b, c, d) with ax + by + cz + d >= 0
+    // on the inside.
+    pub planes: [Vector4<f64>; 6],
+}
+
+impl Frustum {
+    /** Extracts the planes of the frustum from a combined projection and view matrix. */
+    pub fn from_matrix(view_projection: &Matrix4<f64>) -> Frustum {
+        let m = view_projection;
+        let row = |i: usize| match i {
+            0 => Vector4::new(m.x.x, m.y.x, m.z.x, m.w.x),
+            1 => Vector4::new(m.x.y, m.y.y, m.z.y, m.w.y),
+            2 => Vector4::new(m.x.z, m.y.z, m.z.z, m.w.z),
+            _ => Vector4::new(m.x.w, m.y.w, m.z.w, m.w.w),
+        };
+        let plane = |i: usize, sign: f64| {
+            let (r, w) = (row(i), row(3));
+            Vector4::new(w.x + sign * r.x, w.y + sign * r.y, w.z + sign * r.z, w.w + sign * r.w)
+        };
+
+        Frustum {
+            planes: [plane(0, 1.0), plane(0, -1.0), plane(1, 1.0), plane(1, -1.0), plane(2, 1.0), plane(2, -1.0)],
+        }
+    }
+
+    /** Whether an axis aligned box may intersect the frustum, conservative near the corners. */
+    pub fn intersects_aabb(&self, min: Point3<f64>, max: Point3<f64>) -> bool {
+        self.planes.iter().all(|plane| {
+            // The corner of the box furthest along the plane normal.
+            let x = if plane.x >= 0.0 { max.x } else { min.x };
+            let y = if plane.y >= 0.0 { max.y } else { min.y };
+            let z = if plane.z >= 0.0 { max.z } else { min.z };
+            plane.x * x + plane.y * y + plane.z * z + plane.w >= 0.0
+        })
+    }
+}
+
+/** An axis of the terrain plane. */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Axis {
+    X,
+    Y,
+}
+
+/** The weights of the factors contributing to region fertility. */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FertilityParams {
+    pub moisture_weight: f64,
+    pub temperature_weight: f64,
+    pub flatness_weight: f64,
+
+    // The travel distance from water over which moisture decays by a factor of e.
+    pub moisture_range: f64,
+}
+
+impl Default for FertilityParams {
+    fn default() -> FertilityParams {
+        FertilityParams {
+            moisture_weight: 0.4,
+            temperature_weight: 0.3,
+            flatness_weight: 0.3,
+            moisture_range: 50.0,
+        }
+    }
+}
+
+/**
+ * Strategies for generating sites within rectangular bounds, given as the minimum and maximum
+ * corners, at a target density of sites per unit area.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SiteSampler {
+    // Sites no closer than a minimum distance, chosen to roughly match the density, producing
+    // evenly sized regions. Uses Bridson's algorithm.
+    PoissonDisk { bounds: ((f64, f64), (f64, f64)), density: f64 },
+    // Sites placed independently and uniformly at random, producing irregular regions.
+    UniformRandom { bounds: ((f64, f64), (f64, f64)), density: f64 },
+    // One site placed at random within each cell of a square grid.
+    JitteredGrid { bounds: ((f64, f64), (f64, f64)), density: f64 },
+}
+
+impl SiteSampler {
+    /** Generates the sites, the same seed always yields the same sites. */
+    pub fn sample(&self, seed: usize) -> Vec<(f64, f64)> {
+        let seed = seed as u64;
+        // The constant word keeps the seed from being all zeros, which XorShift rejects.
+        let mut rng = XorShiftRng::from_seed([seed as u32, (seed >> 32) as u32, 0x85eb_ca6b, 0xc2b2_ae35]);
+
+        match *self {
+            SiteSampler::PoissonDisk { bounds, density } => poisson_disk_sites(&mut rng, bounds, density),
+            SiteSampler::UniformRandom { bounds: (min, max), density } => {
+                if density <= 0.0 {
+                    return Vec::new();
+                }
+                let count = (density * (max.0 - min.0) * (max.1 - min.1)).round() as usize;
+                (0..count)
+                    .map(|_| {
+                        (min.0 + rng.gen::<f64>() * (max.0 - min.0), min.1 + rng.gen::<f64>() * (max.1 - min.1))
+                    })
+                    .collect()
+            },
+            SiteSampler::JitteredGrid { bounds: (min, max), density } => {
+                if density <= 0.0 {
+                    return Vec::new();
+                }
+                let spacing = 1.0 / density.sqrt();
+                let columns = ((max.0 - min.0) / spacing).ceil() as usize;
+                let rows = ((max.1 - min.1) / spacing).ceil() as usize;
+
+                let mut sites: Vec<(f64, f64)> = Vec::with_capacity(columns * rows);
+                for row in 0..rows {
+                    for column in 0..columns {
+                        // Cells along the far edges are clipped to the bounds.
+                        let x0 = min.0 + column as f64 * spacing;
+                        let y0 = min.1 + row as f64 * spacing;
+                        let x1 = (x0 + spacing).min(max.0);
+                        let y1 = (y0 + spacing).min(max.1);
+                        sites.push((x0 + rng.gen::<f64>() * (x1 - x0), y0 + rng.gen::<f64>() * (y1 - y0)));
+                    }
+                }
+                sites
+            },
+        }
+    }
+
+    /** The same sampler with its bounds shrunk by a margin on every side, down to their center. */
+    fn inset(&self, margin: f64) -> SiteSampler {
+        let inset = |(min, max): ((f64, f64), (f64, f64))| {
+            let center = ((min.0 + max.0) / 2.0, (min.1 + max.1) / 2.0);
+            (((min.0 + margin).min(center.0), (min.1 + margin).min(center.1)),
+             ((max.0 - margin).max(center.0), (max.1 - margin).max(center.1)))
+        };
+
+        match *self {
+            SiteSampler::PoissonDisk { bounds, density } => {
+                SiteSampler::PoissonDisk { bounds: inset(bounds), density }
+            },
+            SiteSampler::UniformRandom { bounds, density } => {
+                SiteSampler::UniformRandom { bounds: inset(bounds), density }
+            },
+            SiteSampler::JitteredGrid { bounds, density } => {
+                SiteSampler::JitteredGrid { bounds: inset(bounds), density }
+            },
+        }
+    }
+}
+
+/** Presets for the large scale arrangement of land and ocean. */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ContinentLayout {
+    // A single central landmass.
+    Pangaea,
+    // Many small islands scattered through the ocean.
+    Archipelago,
+    // A few large landmasses separated by seas.
+    Continents,
+}
+
+impl ContinentLayout {
+    /** The offset of the noise above the water level and the falloff toward the rim. */
+    fn shape(&self) -> (f64, f64) {
+        match *self {
+            ContinentLayout::Pangaea => (0.6, 1.6),
+            ContinentLayout::Archipelago => (0.0, 0.2),
+            ContinentLayout::Continents => (0.2, 1.0),
+        }
+    }
+}
+
+/** Triangle mesh buffers ready for upload to the GPU. */
+pub struct Mesh {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub colors: Vec<[f32; 4]>,
+
+    // Planar texture coordinates, see `VoronoiTerrain::uvs`.
+    pub uvs: Vec<[f32; 2]>,
+
+    // Every 3 indices into the positions, normals, colors and uvs form a triangle.
+    pub indices: Vec<u32>,
+}
+
+impl Mesh {
+    /**
+     * Writes the mesh as a Wavefront OBJ, e.g. for modelling tools.
+     *
+     * Colors are written as RGB after each vertex position, the common extension most tools read,
+     * which drops their alpha.
+     */
+    pub fn write_obj<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        for (position, color) in self.positions.iter().zip(self.colors.iter()) {
+            writeln!(w, "v {} {} {} {} {} {}",
+                     position[0], position[1], position[2], color[0], color[1], color[2])?;
+        }
+        for normal in self.normals.iter() {
+            writeln!(w, "vn {} {} {}", normal[0], normal[1], normal[2])?;
+        }
+        for uv in self.uvs.iter() {
+            writeln!(w, "vt {} {}", uv[0], uv[1])?;
+        }
+        // OBJ indices start from 1.
+        for triangle in self.indices.chunks(3) {
+            let (a, b, c) = (triangle[0] + 1, triangle[1] + 1, triangle[2] + 1);
+            writeln!(w, "f {0}/{0}/{0} {1}/{1}/{1} {2}/{2}/{2}", a, b, c)?;
+        }
+        Ok(())
+    }
+
+    /** Writes the mesh as an ASCII PLY with 8 bit RGBA vertex colors. */
+    pub fn write_ply<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        writeln!(w, "ply")?;
+        writeln!(w, "format ascii 1.0")?;
+        writeln!(w, "element vertex {}", self.positions.len())?;
+        for property in ["x", "y", "z", "nx", "ny", "nz", "s", "t"].iter() {
+            writeln!(w, "property float {}", property)?;
+        }
+        for property in ["red", "green", "blue", "alpha"].iter() {
+            writeln!(w, "property uchar {}", property)?;
+        }
+        writeln!(w, "element face {}", self.indices.len() / 3)?;
+        writeln!(w, "property list uchar uint vertex_indices")?;
+        writeln!(w, "end_header")?;
+
+        for i in 0..self.positions.len() {
+            let (position, normal, uv) = (self.positions[i], self.normals[i], self.uvs[i]);
+            let color: Vec<String> = self.colors[i]
+                .iter()
+                .map(|&channel| ((channel.clamp(0.0, 1.0) * 255.0).round() as u8).to_string())
+                .collect();
+            writeln!(w, "{} {} {} {} {} {} {} {} {}",
+                     position[0], position[1], position[2],
+                     normal[0], normal[1], normal[2],
+                     uv[0], uv[1],
+                     color.join(" "))?;
+        }
+        for triangle in self.indices.chunks(3) {
+            writeln!(w, "3 {} {} {}", triangle[0], triangle[1], triangle[2])?;
+        }
+        Ok(())
+    }
+
+    /**
+     * Writes the mesh as binary glTF 2.0, a .glb, e.g. for game engines and web viewers.
+     *
+     * The binary buffer holds the positions, normals, colors, uvs and indices in that order, the
+     * colors as the `COLOR_0` attribute.
+     */
+    pub fn write_gltf<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        const FLOAT: u32 = 5126;
+        const UNSIGNED_INT: u32 = 5125;
+        const ARRAY_BUFFER: u32 = 34962;
+        const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+        let mut buffer: Vec<u8> = Vec::new();
+        let mut views: Vec<(usize, usize, u32)> = Vec::new();
+        {
+            let mut push_view = |values: Vec<f32>, target: u32| {
+                let offset = buffer.len();
+                for value in values.into_iter() {
+                    buffer.extend_from_slice(&value.to_le_bytes());
+                }
+                views.push((offset, buffer.len() - offset, target));
+            };
+            push_view(self.positions.iter().flat_map(|p| p.iter().cloned()).collect(), ARRAY_BUFFER);
+            push_view(self.normals.iter().flat_map(|n| n.iter().cloned()).collect(), ARRAY_BUFFER);
+            push_view(self.colors.iter().flat_map(|c| c.iter().cloned()).collect(), ARRAY_BUFFER);
+            push_view(self.uvs.iter().flat_map(|uv| uv.iter().cloned()).collect(), ARRAY_BUFFER);
+        }
+        let offset = buffer.len();
+        for &index in self.indices.iter() {
+            buffer.extend_from_slice(&index.to_le_bytes());
+        }
+        views.push((offset, buffer.len() - offset, ELEMENT_ARRAY_BUFFER));
+
+        // The position accessor requires its bounds.
+        let mut min = [f32::INFINITY; 3];
+        let mut max = [f32::NEG_INFINITY; 3];
+        for position in self.positions.iter() {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(position[axis]);
+                max[axis] = max[axis].max(position[axis]);
+            }
+        }
+
+        let count = self.positions.len();
+        let views_json: Vec<String> = views.iter()
+            .map(|&(offset, length, target)| {
+                format!("{{\"buffer\":0,\"byteOffset\":{},\"byteLength\":{},\"target\":{}}}", offset, length, target)
+            })
+            .collect();
+        let accessors_json = [
+            format!("{{\"bufferView\":0,\"componentType\":{},\"count\":{},\"type\":\"VEC3\",\"min\":[{},{},{}],\"max\":[{},{},{}]}}",
+                    FLOAT, count, min[0], min[1], min[2], max[0], max[1], max[2]),
+            format!("{{\"bufferView\":1,\"componentType\":{},\"count\":{},\"type\":\"VEC3\"}}", FLOAT, count),
+            format!("{{\"bufferView\":2,\"componentType\":{},\"count\":{},\"type\":\"VEC4\"}}", FLOAT, count),
+            format!("{{\"bufferView\":3,\"componentType\":{},\"count\":{},\"type\":\"VEC2\"}}", FLOAT, count),
+            format!("{{\"bufferView\":4,\"componentType\":{},\"count\":{},\"type\":\"SCALAR\"}}",
+                    UNSIGNED_INT, self.indices.len()),
+        ];
+        let mut json = format!(
+            "{{\"asset\":{{\"version\":\"2.0\"}},\"scene\":0,\"scenes\":[{{\"nodes\":[0]}}],\"nodes\":[{{\"mesh\":0}}],\
+             \"meshes\":[{{\"primitives\":[{{\"attributes\":{{\"POSITION\":0,\"NORMAL\":1,\"COLOR_0\":2,\"TEXCOORD_0\":3}},\"indices\":4}}]}}],\
+             \"buffers\":[{{\"byteLength\":{}}}],\"bufferViews\":[{}],\"accessors\":[{}]}}",
+            buffer.len(), views_json.join(","), accessors_json.join(","));
+
+        // Chunks are 4 byte aligned, the JSON padded with spaces and the binary with zeros.
+        while !json.len().is_multiple_of(4) {
+            json.push(' ');
+        }
+        while !buffer.len().is_multiple_of(4) {
+            buffer.push(0);
+        }
+
+        let length = 12 + 8 + json.len() + 8 + buffer.len();
+        w.write_all(b"glTF")?;
+        w.write_all(&2u32.to_le_bytes())?;
+        w.write_all(&(length as u32).to_le_bytes())?;
+        w.write_all(&(json.len() as u32).to_le_bytes())?;
+        w.write_all(b"JSON")?;
+        w.write_all(json.as_bytes())?;
+        w.write_all(&(buffer.len() as u32).to_le_bytes())?;
+        w.write_all(b"BIN\0")?;
+        w.write_all(&buffer)
+    }
+}
+
+/** The differences between two terrains, see `VoronoiTerrain::diff`. */
+pub struct TerrainDiff {
+    pub added_vertices: usize,
+    pub removed_vertices: usize,
+    pub moved_vertices: usize,
+
+    pub added_regions: usize,
+    pub removed_regions: usize,
+    pub moved_regions: usize,
+
+    // The largest change in height between vertices present in both terrains.
+    pub max_height_delta: f64,
+}
+
+pub struct Graph<T> {
+    pub vertices: Vec<T>,
+    pub edges: Vec<(usize, usize)>,
+}
+
+impl<T> Graph<T> {
+    /** Builds the list of neighbouring vertex indices for each vertex. */
+    pub fn adjacency(&self) -> Vec<Vec<usize>> {
+        let mut adjacency: Vec<Vec<usize>> = vec![Vec::new(); self.vertices.len()];
+        for &(a, b) in self.edges.iter() {
+            adjacency[a].push(b);
+            adjacency[b].push(a);
+        }
+        adjacency
+    }
+}
+
+/** The center of a set of sites and the distance from it to the corners of their bounds. */
+fn site_extent(sites: &[(f64, f64)]) -> ((f64, f64), f64) {
+    let (min, max) = point_bounds(sites);
+    (((min.0 + max.0) * 0.5, (min.1 + max.1) * 0.5), distance2(min, max).sqrt() * 0.5)
+}
+
+/**
+ * The position of the center of a pixel in a grid spanning bounds.
+ *
+ * @param bounds The minimum and maximum corners of the grid.
+ */
+fn pixel_position(bounds: ((f64, f64), (f64, f64)),
+                  column: u32,
+                  row: u32,
+                  width: u32,
+                  height: u32)
+                  -> (f64, f64) {
+    let (min, max) = bounds;
+    (min.0 + (column as f64 + 0.5) / width as f64 * (max.0 - min.0),
+     min.1 + (row as f64 + 0.5) / height as f64 * (max.1 - min.1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, XorShiftRng};
+
+    /** Sites on an `n` by `n` grid with unit spacing, jittered so no four are cocircular. */
+    fn grid_sites(n: usize) -> Vec<(f64, f64)> {
+        let mut rng = XorShiftRng::from_seed([1, 2, 3, 4]);
+        (0..n * n)
+            .map(|i| {
+                let jitter = (rng.gen::<f64>() * 0.2 - 0.1, rng.gen::<f64>() * 0.2 - 0.1);
+                ((i % n) as f64 + jitter.0, (i / n) as f64 + jitter.1)
+            })
+            .collect()
+    }
+
+    fn build_grid(n: usize) -> VoronoiTerrain {
+        VoronoiTerrain::builder()
+            .set_seed(7)
+            .set_sites(grid_sites(n))
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn bounding_sphere_encloses_all_vertices() {
+        let terrain = build_grid(8);
+        let (center, radius) = terrain.bounding_sphere();
+
+        for vertex in terrain.terrain_graph.vertices.iter() {
+            assert!((vertex.position - center).magnitude() <= radius * (1.0 + 1e-9));
+        }
+    }
+
+    #[test]
+    fn bounding_sphere_is_tight() {
+        let terrain = build_grid(8);
+        let (_, radius) = terrain.bounding_sphere();
+
+        // The minimal sphere's radius lies between half the diameter of the points and the
+        // diameter over root 3, Ritter's is typically within ~5% of it.
+        let positions: Vec<Point3<f64>> = terrain.terrain_graph.vertices.iter().map(|v| v.position).collect();
+        let diameter = positions.iter()
+            .flat_map(|a| positions.iter().map(move |b| (*a - *b).magnitude()))
+            .fold(0.0, f64::max);
+        assert!(radius >= diameter * 0.5 * (1.0 - 1e-9));
+        assert!(radius <= diameter / 3f64.sqrt() * 1.1);
+    }
+
+    #[test]
+    fn builds_of_the_same_sites_are_identical() {
+        let (a, b) = (build_grid(8), build_grid(8));
+
+        let positions = |terrain: &VoronoiTerrain| -> Vec<Point3<f64>> {
+            terrain.terrain_graph.vertices.iter().map(|vertex| vertex.position).collect()
+        };
+        assert_eq!(positions(&a), positions(&b));
+        assert_eq!(a.terrain_graph.edges, b.terrain_graph.edges);
+        assert_eq!(a.region_graph.edges, b.region_graph.edges);
+    }
+
+    /** Buckets vertex heights into twentieths of [-2, 2], which bounds fbm noise. */
+    fn height_histogram(terrain: &VoronoiTerrain) -> Vec<usize> {
+        let mut histogram = vec![0; 20];
+        for vertex in terrain.terrain_graph.vertices.iter() {
+            let bucket = ((vertex.position.z + 2.0) / 4.0 * 20.0).max(0.0) as usize;
+            histogram[bucket.min(19)] += 1;
+        }
+        histogram
+    }
+
+    #[test]
+    fn height_feedback_changes_height_histogram() {
+        let plain = build_grid(10);
+        let warped = VoronoiTerrain::builder()
+            .set_seed(7)
+            .set_sites(grid_sites(10))
+            .set_height_feedback(2.0)
+            .build()
+            .unwrap();
+
+        assert_eq!(plain.terrain_graph.vertices.len(), warped.terrain_graph.vertices.len());
+        assert!(height_histogram(&plain) != height_histogram(&warped));
+    }
+
+    #[test]
+    fn regions_by_degree_ranks_interior_above_corners() {
+        let n = 6;
+        let terrain = build_grid(n);
+        let degrees = terrain.regions_by_degree();
+        let degree_of = |region: usize| degrees.iter().find(|&&(r, _)| r == region).unwrap().1;
+
+        assert_eq!(degrees.len(), n * n);
+        assert!(degrees.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+
+        // Hull regions may also neighbour distant hull regions, so compare the averages.
+        let average = |regions: &[usize]| {
+            regions.iter().map(|&region| degree_of(region)).sum::<usize>() as f64 / regions.len() as f64
+        };
+        let interior: Vec<usize> = (0..n * n)
+            .filter(|&i| i % n > 0 && i % n < n - 1 && i / n > 0 && i / n < n - 1)
+            .collect();
+        assert!(average(&interior) > average(&[0, n - 1, n * (n - 1), n * n - 1]));
+    }
+
+    #[test]
+    fn flow_accumulation_is_conserved_at_sinks() {
+        let terrain = build_grid(10);
+        let accumulation = terrain.flow_accumulation();
+        let flow_targets = terrain.terrain_flow_targets();
+
+        // Every vertex drains into exactly one sink, counting itself.
+        let drained: f64 = flow_targets.iter()
+            .zip(accumulation.iter())
+            .filter(|&(target, _)| target.is_none())
+            .map(|(_, &upstream)| upstream + 1.0)
+            .sum();
+        assert_eq!(drained, terrain.terrain_graph.vertices.len() as f64);
+
+        // A vertex carries at least the flow of each vertex draining into it.
+        for (vertex_index, target) in flow_targets.iter().enumerate() {
+            if let Some(target) = *target {
+                assert!(accumulation[target] >= accumulation[vertex_index] + 1.0);
+            }
+        }
+    }
+
+    /** Whether no site other than the endpoints lies strictly within the edge's diametral circle. */
+    fn is_gabriel(sites: &[(f64, f64)], a: usize, b: usize) -> bool {
+        let center = ((sites[a].0 + sites[b].0) * 0.5, (sites[a].1 + sites[b].1) * 0.5);
+        let radius2 = distance2(sites[a], sites[b]) * 0.25;
+        (0..sites.len()).filter(|&c| c != a && c != b).all(|c| distance2(sites[c], center) >= radius2)
+    }
+
+    #[test]
+    fn gabriel_edges_are_delaunay_edges() {
+        let terrain = build_grid(6);
+        let delaunay_edges = terrain.delaunay_edges();
+        let gabriel_edges = terrain.gabriel_edges();
+
+        assert!(!gabriel_edges.is_empty());
+        assert!(gabriel_edges.len() < delaunay_edges.len());
+        for edge in gabriel_edges.iter() {
+            assert!(delaunay_edges.contains(edge));
+        }
+    }
+
+    #[test]
+    fn gabriel_edges_match_brute_force() {
+        let sites = grid_sites(6);
+        let terrain = build_grid(6);
+        let gabriel_edges = terrain.gabriel_edges();
+
+        for &(a, b) in terrain.delaunay_edges().iter() {
+            assert_eq!(gabriel_edges.contains(&(a, b)), is_gabriel(&sites, a, b));
+        }
+    }
+
+    #[test]
+    fn relative_neighborhood_edges_are_gabriel_edges() {
+        let sites = grid_sites(6);
+        let terrain = build_grid(6);
+        let gabriel_edges = terrain.gabriel_edges();
+        let relative_neighborhood_edges = terrain.relative_neighborhood_edges();
+
+        assert!(!relative_neighborhood_edges.is_empty());
+        for &(a, b) in relative_neighborhood_edges.iter() {
+            assert!(gabriel_edges.contains(&(a, b)));
+
+            // No third site is closer to both endpoints than they are to each other.
+            let length2 = distance2(sites[a], sites[b]);
+            assert!((0..sites.len())
+                .filter(|&c| c != a && c != b)
+                .all(|c| distance2(sites[a], sites[c]).max(distance2(sites[b], sites[c])) >= length2));
+        }
+    }
+
+    /**
+     * Builds the grid of `grid_sites` with each site's height given by its column and row.
+     *
+     * Each terrain vertex takes the average height of the sites generating its regions, the
+     * centers and normals then follow from the vertices as in the build.
+     */
+    fn build_grid_with_heights<F>(n: usize, height: F) -> VoronoiTerrain
+        where F: Fn(usize, usize) -> f64
+    {
+        let mut terrain = build_grid(n);
+
+        let mut sums: Vec<(f64, usize)> = vec![(0.0, 0); terrain.terrain_graph.vertices.len()];
+        for (region_index, region) in terrain.region_graph.vertices.iter().enumerate() {
+            for &i in region.vertices.iter() {
+                sums[i].0 += height(region_index % n, region_index / n);
+                sums[i].1 += 1;
+            }
+        }
+        for (vertex, &(sum, count)) in terrain.terrain_graph.vertices.iter_mut().zip(sums.iter()) {
+            vertex.position.z = sum / count as f64;
+        }
+
+        update_geometry(&mut terrain.terrain_graph, &mut terrain.region_graph);
+        terrain
+    }
+
+    #[test]
+    fn cliff_region_is_rougher_than_flat_region() {
+        let n = 8;
+        let terrain = build_grid_with_heights(n, |column, _| if column >= 4 { 0.8 } else { 0.0 });
+
+        // Both regions are in the same row, the cliff region borders the raised columns.
+        let flat = 3 * n + 1;
+        let cliff = 3 * n + 3;
+        assert!(terrain.region_roughness(flat) < 1e-9);
+        assert!(terrain.region_roughness(cliff) > 0.1);
+    }
+
+    #[test]
+    fn region_without_vertices_has_no_roughness() {
+        let mut terrain = build_grid(4);
+        terrain.region_graph.vertices[5].vertices.clear();
+        assert_eq!(terrain.region_roughness(5), 0.0);
+    }
+
+    #[test]
+    fn concentric_elevation_lowers_each_band() {
+        let center = (4.5, 4.5);
+        let plain = build_grid(10);
+        let banded = VoronoiTerrain::builder()
+            .set_seed(7)
+            .set_sites(grid_sites(10))
+            .set_concentric_elevation(center, 0.1, 1.5)
+            .build()
+            .unwrap();
+
+        for (plain, banded) in plain.terrain_graph.vertices.iter().zip(banded.terrain_graph.vertices.iter()) {
+            let position = (plain.position.x, plain.position.y);
+            let band = (distance2(position, center).sqrt() / 1.5).floor();
+            assert!((plain.position.z - band * 0.1 - banded.position.z).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn concentric_elevation_ignores_empty_bands() {
+        let plain = build_grid(6);
+        let banded = VoronoiTerrain::builder()
+            .set_seed(7)
+            .set_sites(grid_sites(6))
+            .set_concentric_elevation((2.5, 2.5), 0.1, 0.0)
+            .build()
+            .unwrap();
+
+        for (plain, banded) in plain.terrain_graph.vertices.iter().zip(banded.terrain_graph.vertices.iter()) {
+            assert_eq!(plain.position, banded.position);
+        }
+    }
+
+    #[test]
+    fn diff_of_reseeded_terrain_only_changes_heights() {
+        let terrain = build_grid(8);
+        let reseeded = VoronoiTerrain::builder()
+            .set_seed(8)
+            .set_sites(grid_sites(8))
+            .build()
+            .unwrap();
+
+        let diff = terrain.diff(&reseeded);
+        assert!(diff.max_height_delta > 0.0);
+        assert_eq!((diff.added_vertices, diff.removed_vertices, diff.moved_vertices), (0, 0, 0));
+        assert_eq!((diff.added_regions, diff.removed_regions, diff.moved_regions), (0, 0, 0));
+
+        assert_eq!(terrain.diff(&build_grid(8)).max_height_delta, 0.0);
+    }
+
+    #[test]
+    fn height_at_outside_terrain_uses_fallback() {
+        let mut builder = VoronoiTerrain::builder();
+        builder.set_seed(7).set_sites(grid_sites(6));
+
+        let terrain = builder.build().unwrap();
+        assert!(terrain.height_at(2.5, 2.5).is_some());
+        assert_eq!(terrain.height_at(1e6, 1e6), None);
+
+        let terrain = builder.set_out_of_bounds_height(Some(-0.5)).build().unwrap();
+        assert_eq!(terrain.height_at(1e6, 1e6), Some(-0.5));
+        assert!(terrain.height_at(2.5, 2.5) != Some(-0.5));
+    }
+
+    #[test]
+    fn geodesic_distance_exceeds_planar_distance_over_hills() {
+        let terrain = build_grid_with_heights(8, |column, _| if column % 2 == 0 { 0.0 } else { 0.8 });
+        let (from, to) = ((1.5, 3.5), (5.5, 3.5));
+
+        let geodesic = terrain.geodesic_distance(from, to).unwrap();
+        assert!(geodesic > distance2(from, to).sqrt());
+        assert_eq!(terrain.geodesic_distance(from, (1e6, 1e6)), None);
+    }
+
+    #[test]
+    fn rasterize_region_ids_finds_every_region() {
+        let n = 6;
+        let terrain = build_grid(n);
+        let ids = terrain.rasterize_region_ids(60, 60);
+        assert_eq!(ids.len(), 60 * 60);
+
+        let mut distinct = ids.clone();
+        distinct.sort();
+        distinct.dedup();
+        assert_eq!(distinct, (0..(n * n) as u32).collect::<Vec<u32>>());
+
+        let bounds = terrain.site_bounds();
+        for (i, &id) in ids.iter().enumerate() {
+            let (x, y) = pixel_position(bounds, (i % 60) as u32, (i / 60) as u32, 60, 60);
+            assert_eq!(Some(id as usize), terrain.region_of(x, y));
+        }
+    }
+
+    #[test]
+    fn mirror_reflects_heights() {
+        let mut terrain = build_grid(8);
+        terrain.mirror(Axis::X, 3.5);
+
+        // Sites mirrored across the line are cocircular with their reflections, so the cells
+        // along it may be triangulated asymmetrically, sample away from them.
+        for &(x, y) in [(1.2, 2.3), (0.4, 5.9), (2.1, 4.4), (1.6, 0.7)].iter() {
+            let height = terrain.height_at(x, y).unwrap();
+            let reflected = terrain.height_at(7.0 - x, y).unwrap();
+            assert!((height - reflected).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn tiny_build_timeout_times_out() {
+        let result = VoronoiTerrain::builder()
+            .set_sites(grid_sites(30))
+            .set_build_timeout(Duration::new(0, 0))
+            .build();
+        assert_eq!(result.err(), Some(TerrainBuildError::Timeout));
+
+        let result = VoronoiTerrain::builder()
+            .set_sites(grid_sites(5))
+            .set_build_timeout(Duration::from_secs(60))
+            .build();
+        assert!(result.is_ok());
+    }
+
+    /** Heights of two round hills centered on (2, 4) and (7, 4), separated by a pass. */
+    fn two_peaks(column: usize, row: usize) -> f64 {
+        let hill = |center: (f64, f64)| (-distance2((column as f64, row as f64), center) / 4.0).exp();
+        hill((2.0, 4.0)) + hill((7.0, 4.0)) - 0.5
+    }
+
+    #[test]
+    fn saddle_points_find_the_pass_between_two_peaks() {
+        let terrain = build_grid_with_heights(10, two_peaks);
+        let vertices = &terrain.terrain_graph.vertices;
+
+        let saddles = terrain.saddle_points();
+        assert!(saddles.iter().any(|&i| {
+            distance2((vertices[i].position.x, vertices[i].position.y), (4.5, 4.0)) < 1.0
+        }));
+    }
+
+    #[test]
+    fn score_regions_by_area_matches_region_areas() {
+        let terrain = build_grid(6);
+        let vertices = &terrain.terrain_graph.vertices;
+
+        let scores = terrain.score_regions(|region| {
+            let polygon: Vec<(f64, f64)> = region.vertices.iter()
+                .map(|&i| (vertices[i].position.x, vertices[i].position.y))
+                .collect();
+            polygon_area(&clip_polygon_to_bounds(&polygon, terrain.site_bounds()))
+        });
+        assert_eq!(scores, terrain.region_areas());
+    }
+
+    #[test]
+    fn tangents_are_orthogonal_to_normals() {
+        let terrain = build_grid_with_heights(8, two_peaks);
+        let tangents = terrain.compute_tangents();
+
+        for (tangent, vertex) in tangents.iter().zip(terrain.terrain_graph.vertices.iter()) {
+            assert!((vertex.normal.magnitude() - 1.0).abs() < 1e-9);
+            assert!(vertex.normal.z > 0.0);
+            assert!((tangent.magnitude() - 1.0).abs() < 1e-9);
+            assert!(tangent.dot(vertex.normal).abs() < 1e-9);
+        }
+        for region in terrain.region_graph.vertices.iter() {
+            assert!((region.normal.magnitude() - 1.0).abs() < 1e-9);
+            assert!(region.normal.z > 0.0);
+        }
+    }
+
+    #[test]
+    fn uvs_span_the_site_bounds() {
+        let terrain = build_grid(6);
+        let uvs = terrain.uvs();
+        let (min, max) = terrain.site_bounds();
+        let expected = |t: f64, low: f64, high: f64| ((t - low) / (high - low)).clamp(0.0, 1.0);
+
+        for (uv, vertex) in uvs.iter().zip(terrain.terrain_graph.vertices.iter()) {
+            assert!((uv[0] as f64 - expected(vertex.position.x, min.0, max.0)).abs() < 1e-6);
+            assert!((uv[1] as f64 - expected(vertex.position.y, min.1, max.1)).abs() < 1e-6);
+        }
+
+        // The outer vertices lie far beyond the sites, those past a corner map onto it.
+        let corners = [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0], [1.0, 1.0]];
+        assert!(uvs.iter().any(|uv| corners.contains(uv)));
+    }
+
+    #[test]
+    fn neighbouring_chunks_share_border_heights() {
+        // Sites repeat every 5 units and overhang the chunk by a unit on each side, so both chunks
+        // have the same sites, and so the same terrain vertices, around their shared border.
+        let mut rng = XorShiftRng::from_seed([1, 2, 3, 4]);
+        let jitter: Vec<(f64, f64)> = (0..25)
+            .map(|_| (rng.gen::<f64>() * 0.2 - 0.1, rng.gen::<f64>() * 0.2 - 0.1))
+            .collect();
+        let sites = (-1..7)
+            .flat_map(|x: i32| (0..5).map(move |y: i32| (x, y)))
+            .map(|(x, y)| {
+                let (jx, jy) = jitter[(((x + 5) % 5) * 5 + y) as usize];
+                (x as f64 + jx, y as f64 + jy)
+            })
+            .collect();
+
+        let mut builder = VoronoiTerrain::builder();
+        builder.set_seed(7).set_sites(sites).set_continent_layout(ContinentLayout::Pangaea);
+        let left = builder.build_chunk(0, 0, 5.0).unwrap();
+        let right = builder.build_chunk(1, 0, 5.0).unwrap();
+
+        let mut shared = 0;
+        for vertex in left.terrain_graph.vertices.iter().filter(|v| (v.position.x - 5.0).abs() < 0.5) {
+            let p = vertex.position;
+            if p.y < 1.0 || p.y > 3.0 {
+                continue;
+            }
+            if let Some(other) = right.terrain_graph.vertices.iter()
+                .find(|v| distance2((v.position.x, v.position.y), (p.x, p.y)) < 1e-12) {
+                assert!((other.position.z - p.z).abs() < 1e-9);
+                shared += 1;
+            }
+        }
+        assert!(shared > 0);
+    }
+
+    #[test]
+    fn sorted_vertex_edges_are_in_angular_order() {
+        let terrain = VoronoiTerrain::builder()
+            .set_seed(7)
+            .set_sites(grid_sites(6))
+            .set_sort_vertex_edges(true)
+            .build()
+            .unwrap();
+        let graph = &terrain.terrain_graph;
+
+        for (vertex_index, vertex) in graph.vertices.iter().enumerate() {
+            let angles: Vec<f64> = vertex.edges.iter()
+                .map(|&edge_index| {
+                    let (a, b) = graph.edges[edge_index];
+                    let other = if a == vertex_index { b } else { a };
+                    let offset = graph.vertices[other].position - vertex.position;
+                    offset.y.atan2(offset.x)
+                })
+                .collect();
+            assert!(angles.windows(2).all(|pair| pair[0] <= pair[1]));
+        }
+    }
+
+    #[test]
+    fn vertex_edges_reference_their_vertex() {
+        let terrain = build_grid(6);
+        let graph = &terrain.terrain_graph;
+
+        // Each edge is listed by its vertices, by both if it lies between two regions.
+        let mut listed = vec![0; graph.edges.len()];
+        for (vertex_index, vertex) in graph.vertices.iter().enumerate() {
+            for &edge_index in vertex.edges.iter() {
+                let (a, b) = graph.edges[edge_index];
+                assert!(a == vertex_index || b == vertex_index);
+                listed[edge_index] += 1;
+            }
+        }
+        let mut bounded: HashMap<(usize, usize), usize> = HashMap::new();
+        for region in terrain.region_graph.vertices.iter() {
+            for i in 0..region.vertices.len() {
+                let (a, b) = (region.vertices[i], region.vertices[(i + 1) % region.vertices.len()]);
+                *bounded.entry((a.min(b), a.max(b))).or_insert(0) += 1;
+            }
+        }
+        for (edge_index, &(a, b)) in graph.edges.iter().enumerate() {
+            assert_eq!(listed[edge_index], bounded[&(a.min(b), a.max(b))]);
+        }
+    }
+
+    #[test]
+    fn euler_characteristic_detects_corruption() {
+        let mut terrain = build_grid(6);
+        assert_eq!(terrain.euler_characteristic(), 1);
+
+        // Duplicated edges are a typical symptom of a broken triangulation.
+        let edge = terrain.terrain_graph.edges[0];
+        terrain.terrain_graph.edges.push(edge);
+        assert_eq!(terrain.euler_characteristic(), 0);
+    }
+
+    #[test]
+    fn normalize_fbm_scales_by_octave_amplitudes() {
+        assert!((normalize_fbm(1.96875) - 1.0).abs() < 1e-12);
+        assert!((normalize_fbm(-0.984375) + 0.5).abs() < 1e-12);
+        assert_eq!(normalize_fbm(3.0), NOISE_RANGE.1);
+        assert_eq!(normalize_fbm(-3.0), NOISE_RANGE.0);
+
+        let noise = Fbm::new().set_seed(3);
+        let heights: Vec<f64> = (0..2500)
+            .map(|i| normalize_fbm(noise.get([(i % 50) as f64 * 0.37, (i / 50) as f64 * 0.37])))
+            .collect();
+        assert!(heights.iter().all(|&h| h >= NOISE_RANGE.0 && h <= NOISE_RANGE.1));
+        assert!(heights.iter().any(|&h| h < 0.0) && heights.iter().any(|&h| h > 0.0));
+    }
+
+    #[test]
+    fn tall_wall_casts_a_shadow() {
+        let n = 9;
+        let terrain = build_grid_with_heights(n, |column, _| if column == 4 { 0.9 } else { 0.0 });
+
+        // A low light to the east shadows the west side of the wall.
+        let shadowed = terrain.cast_shadows(Vector3::new(1.0, 0.0, 0.2));
+        assert!(shadowed[4 * n + 2]);
+        assert!(!shadowed[4 * n + 6]);
+
+        assert!(terrain.cast_shadows(Vector3::new(0.0, 0.0, 1.0)).iter().all(|&s| !s));
+    }
+
+    #[test]
+    fn region_areas_cover_the_site_bounds() {
+        let terrain = build_grid(6);
+        let (min, max) = terrain.site_bounds();
+
+        let total: f64 = terrain.region_areas().iter().sum();
+        assert!((total - (max.0 - min.0) * (max.1 - min.1)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn raising_the_water_level_increases_water_volume() {
+        let mut terrain = build_grid_with_heights(8, two_peaks);
+
+        let mut previous = 0.0;
+        for &water_level in [30, 40, 50, 60, 70].iter() {
+            terrain.water_level = water_level;
+            let volume = terrain.water_volume();
+            assert!(volume > previous);
+            previous = volume;
+        }
+    }
+
+    #[test]
+    fn vertex_post_process_flattening_gives_flat_terrain() {
+        let terrain = VoronoiTerrain::builder()
+            .set_seed(7)
+            .set_sites(grid_sites(6))
+            .set_vertex_post_process(|_, vertex| vertex.position.z = 0.0)
+            .build()
+            .unwrap();
+
+        assert!(terrain.terrain_graph.vertices.iter().all(|vertex| vertex.position.z == 0.0));
+        for region in terrain.region_graph.vertices.iter() {
+            assert_eq!(region.center.z, 0.0);
+            assert!((region.normal - Vector3::new(0.0, 0.0, 1.0)).magnitude() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn region_distance_matrix_is_symmetric_with_zero_diagonal() {
+        let terrain = build_grid_with_heights(5, two_peaks);
+        let matrix = terrain.region_distance_matrix();
+
+        assert_eq!(matrix.len(), 25);
+        for (a, row) in matrix.iter().enumerate() {
+            assert_eq!(row[a], 0.0);
+            for (b, &distance) in row.iter().enumerate() {
+                assert!((distance - matrix[b][a]).abs() < 1e-9);
+                assert!(distance.is_finite());
+            }
+        }
+    }
+
+    #[test]
+    fn spread_regions_pick_opposite_corners() {
+        let n = 6;
+        let terrain = build_grid(n);
+        let sites = grid_sites(n);
+
+        let selected = terrain.select_spread_regions(2);
+        assert_eq!(selected.len(), 2);
+
+        // The diagonal of the grid is the furthest apart any two sites can be.
+        let diagonal = ((n - 1) as f64) * 2f64.sqrt();
+        assert!(distance2(sites[selected[0]], sites[selected[1]]).sqrt() > diagonal * 0.9);
+
+        assert_eq!(terrain.select_spread_regions(100).len(), n * n);
+    }
+
+    #[test]
+    fn set_vertex_height_only_updates_affected_regions() {
+        let mut terrain = build_grid_with_heights(8, two_peaks);
+        let regions: Vec<(Point3<f64>, Vector3<f64>)> = terrain.region_graph.vertices
+            .iter()
+            .map(|region| (region.center, region.normal))
+            .collect();
+
+        // A vertex in the middle of the terrain, bounding three regions.
+        let v = terrain.region_graph.vertices[3 * 8 + 3].vertices[0];
+        let mut expected: Vec<usize> = (0..terrain.region_graph.vertices.len())
+            .filter(|&region| terrain.region_graph.vertices[region].vertices.contains(&v))
+            .collect();
+
+        let mut changed = terrain.set_vertex_height(v, 0.9);
+        changed.sort();
+        expected.sort();
+        assert_eq!(changed, expected);
+
+        for (region_index, region) in terrain.region_graph.vertices.iter().enumerate() {
+            let (center, normal) = regions[region_index];
+            if expected.contains(&region_index) {
+                assert!(region.center.z > center.z);
+            } else {
+                assert_eq!((region.center, region.normal), (center, normal));
+            }
+        }
+
+        // The incremental update matches recalculating everything.
+        let incremental: Vec<Vector3<f64>> = terrain.terrain_graph.vertices.iter().map(|v| v.normal).collect();
+        update_geometry(&mut terrain.terrain_graph, &mut terrain.region_graph);
+        for (normal, vertex) in incremental.iter().zip(terrain.terrain_graph.vertices.iter()) {
+            assert!((*normal - vertex.normal).magnitude() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn flat_terrain_bakes_to_the_up_normal() {
+        let terrain = VoronoiTerrain::builder()
+            .set_sites(grid_sites(6))
+            .set_vertex_post_process(|_, vertex| vertex.position.z = 0.0)
+            .build()
+            .unwrap();
+
+        let pixels = terrain.bake_normal_map(16, 8);
+        assert_eq!(pixels.len(), 16 * 8);
+        assert!(pixels.iter().all(|&pixel| pixel == [128, 128, 255]));
+    }
+
+    #[test]
+    fn label_anchors_lie_inside_their_regions() {
+        let terrain = build_grid_with_heights(6, two_peaks);
+
+        for region in 0..terrain.region_graph.vertices.len() {
+            let anchor = terrain.label_anchor(region);
+            assert!(point_in_polygon((anchor.x, anchor.y), &terrain.region_polygon(region)));
+        }
+    }
+
+    #[test]
+    fn peak_prominence_of_two_peaks_meets_at_the_saddle() {
+        // A tall hill at (2, 4) and a shorter one at (7, 4), the pass between them is about 0.27.
+        let terrain = build_grid_with_heights(9, |column, row| {
+            let hill = |center: (f64, f64)| (-distance2((column as f64, row as f64), center) / 4.0).exp();
+            0.8 * hill((2.0, 4.0)) + 0.5 * hill((7.0, 4.0))
+        });
+        let vertices = &terrain.terrain_graph.vertices;
+
+        let prominence = terrain.peak_prominence();
+        assert!(prominence.windows(2).all(|pair| pair[0].1 >= pair[1].1));
+
+        let near = |peak: usize, x: f64| {
+            distance2((vertices[peak].position.x, vertices[peak].position.y), (x, 4.0)) < 1.0
+        };
+        let (first, first_prominence) = prominence[0];
+        let (second, second_prominence) = prominence[1];
+        assert!(near(first, 2.0) && near(second, 7.0));
+
+        // The highest peak is measured to the lowest vertex, the second to the pass between them.
+        let lowest = vertices.iter().map(|v| v.position.z).fold(f64::INFINITY, f64::min);
+        assert!((first_prominence - (vertices[first].position.z - lowest)).abs() < 1e-12);
+        let saddle = vertices[second].position.z - second_prominence;
+        assert!(saddle > 0.15 && saddle < 0.35);
+    }
+
+    /** The mean and standard deviation of the heights of the vertices within the site bounds. */
+    fn height_stats(terrain: &VoronoiTerrain) -> (f64, f64) {
+        let (min, max) = terrain.site_bounds();
+        let heights: Vec<f64> = terrain.terrain_graph.vertices
+            .iter()
+            .map(|vertex| vertex.position)
+            .filter(|p| p.x >= min.0 && p.x <= max.0 && p.y >= min.1 && p.y <= max.1)
+            .map(|p| p.z)
+            .collect();
+        let mean = heights.iter().sum::<f64>() / heights.len() as f64;
+        let variance = heights.iter().map(|h| (h - mean) * (h - mean)).sum::<f64>() / heights.len() as f64;
+        (mean, variance.sqrt())
+    }
+
+    #[test]
+    fn preview_resembles_the_full_build() {
+        let mut builder = VoronoiTerrain::builder();
+        builder.set_seed(7).set_sites(grid_sites(20));
+        let full = builder.build().unwrap();
+        let preview = builder.build_preview(0.25).unwrap();
+
+        assert_eq!(full.region_graph.vertices.len(), 400);
+        assert_eq!(preview.region_graph.vertices.len(), 100);
+
+        let (full_mean, full_deviation) = height_stats(&full);
+        let (preview_mean, preview_deviation) = height_stats(&preview);
+        assert!((full_mean - preview_mean).abs() < 0.1);
+        assert!((full_deviation - preview_deviation).abs() < 0.1);
+    }
+
+    #[test]
+    fn uphill_neighbour_is_highest_on_a_ramp() {
+        let terrain = build_grid_with_heights(10, |column, _| column as f64 * 0.1 - 0.5);
+
+        let heights = terrain.neighbor_heights(4.5, 4.5, 1.0);
+        let heights: Vec<f64> = heights.iter().map(|height| height.unwrap()).collect();
+        assert!(heights[2] > heights[0]);
+        assert!(heights[2] > heights[1]);
+        assert!(heights[2] > heights[3]);
+        assert!(heights[3] < heights[0]);
+        assert!(heights[3] < heights[1]);
+    }
+
+    #[test]
+    fn region_graph_diameter_of_a_line_spans_it() {
+        let sites: Vec<(f64, f64)> = (0..8).map(|i| (i as f64, 0.0)).collect();
+        let terrain = VoronoiTerrain::builder()
+            .set_seed(7)
+            .set_sites(sites)
+            .build()
+            .unwrap();
+
+        assert_eq!(terrain.region_graph_diameter(), 7);
+    }
+
+    #[test]
+    fn flat_terrain_is_level_at_the_water_height() {
+        let terrain = VoronoiTerrain::builder()
+            .set_seed(7)
+            .set_sites(grid_sites(6))
+            .set_height(10)
+            .set_water_level(6)
+            .set_flat(true)
+            .build()
+            .unwrap();
+
+        let water_height = terrain.water_height();
+        assert!(terrain.terrain_graph.vertices.iter().all(|vertex| vertex.position.z == water_height));
+        for region in terrain.region_graph.vertices.iter() {
+            assert_eq!(region.center.z, water_height);
+            assert!((region.normal - Vector3::new(0.0, 0.0, 1.0)).magnitude() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn region_edge_height_deltas_are_large_across_a_step() {
+        let n = 10;
+        let terrain = build_grid_with_heights(n, |column, _| if column < 5 { -0.5 } else { 0.5 });
+
+        // Center heights average the vertices, so only regions clear of the step are level.
+        let interior = |region: usize| {
+            let (column, row) = (region % n, region / n);
+            column > 0 && column < n - 1 && row > 0 && row < n - 1
+        };
+        let deltas = terrain.region_edge_height_deltas();
+        assert_eq!(deltas.len(), terrain.region_graph.edges.len());
+        for (&(a, b), &delta) in terrain.region_graph.edges.iter().zip(deltas.iter()) {
+            if !interior(a) || !interior(b) {
+                continue;
+            }
+            if a % n < 4 && b % n < 4 || a % n > 5 && b % n > 5 {
+                assert!(delta < 1e-9);
+            } else if a % n == 4 && b % n == 5 || a % n == 5 && b % n == 4 {
+                assert!(delta > 0.3);
+            }
+        }
+    }
+
+    #[test]
+    fn vertex_triangles_are_the_triangles_including_the_vertex() {
+        let terrain = build_grid(6);
+        let triangles = terrain.triangles();
+
+        for v in 0..terrain.terrain_graph.vertices.len() {
+            let vertex_triangles = terrain.vertex_triangles(v);
+            assert!(vertex_triangles.iter().all(|triangle| triangle.contains(&v)));
+            // Every region bounded by the vertex contributes at least one triangle to its fan.
+            assert!(vertex_triangles.len() >= terrain.regions_by_vertex()[v].len());
+            let expected = triangles.iter().filter(|triangle| triangle.contains(&v)).count();
+            assert_eq!(vertex_triangles.len(), expected);
+        }
+    }
+
+    #[test]
+    fn visible_regions_are_hidden_behind_a_ridge() {
+        let n = 10;
+        let terrain = build_grid_with_heights(n, |column, _| if column == 5 { 0.9 } else { -0.5 });
+
+        let visible = terrain.visible_regions((2.0, 4.5), 0.1, 20.0);
+        assert!(visible.contains(&(4 * n + 3)));
+        assert!(!visible.contains(&(4 * n + 8)));
+    }
+
+    #[test]
+    fn steepest_region_is_tilted() {
+        let mut terrain = build_grid_with_heights(6, |_, _| 0.0);
+        let v = terrain.region_graph.vertices[14].vertices[0];
+        terrain.set_vertex_height(v, 0.8);
+
+        let steepest = terrain.steepest_region().unwrap();
+        assert!(terrain.regions_by_vertex()[v].contains(&steepest));
+        assert!(terrain.region_graph.vertices[steepest].normal.z < 1.0 - 1e-6);
+    }
+
+    #[test]
+    fn steepest_region_of_no_regions_is_none() {
+        let mut terrain = build_grid(6);
+        terrain.region_graph.vertices.clear();
+        assert_eq!(terrain.steepest_region(), None);
+    }
+
+    /** Finds the terrain vertex nearest to a planar position. */
+    fn nearest_vertex(terrain: &VoronoiTerrain, position: (f64, f64)) -> &TerrainVertex {
+        terrain.terrain_graph.vertices
+            .iter()
+            .min_by(|a, b| {
+                distance2((a.position.x, a.position.y), position)
+                    .total_cmp(&distance2((b.position.x, b.position.y), position))
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn control_point_pins_its_center_height() {
+        let center = nearest_vertex(&build_grid(6), (2.5, 2.5)).position;
+
+        let controlled = VoronoiTerrain::builder()
+            .set_seed(7)
+            .set_sites(grid_sites(6))
+            .add_control_point(center.x, center.y, 0.75, 1.5)
+            .build()
+            .unwrap();
+
+        let vertex = nearest_vertex(&controlled, (center.x, center.y));
+        assert_eq!((vertex.position.x, vertex.position.y), (center.x, center.y));
+        assert_eq!(vertex.position.z, 0.75);
+    }
+
+    #[test]
+    fn control_point_without_radius_is_ignored() {
+        let terrain = build_grid(6);
+        let controlled = VoronoiTerrain::builder()
+            .set_seed(7)
+            .set_sites(grid_sites(6))
+            .add_control_point(2.5, 2.5, 0.75, 0.0)
+            .build()
+            .unwrap();
+
+        for vertex in terrain.terrain_graph.vertices.iter() {
+            let position = vertex.position;
+            assert_eq!(nearest_vertex(&controlled, (position.x, position.y)).position, position);
+        }
+    }
+
+    #[test]
+    fn snow_lies_lower_on_north_facing_slopes() {
+        let n = 10;
+        let north_facing = build_grid_with_heights(n, |_, row| -0.2 * (row as f64 - 4.0));
+        let south_facing = build_grid_with_heights(n, |_, row| 0.2 * (row as f64 - 4.0));
+
+        // With the base snow line at the region's own height only its aspect and latitude decide.
+        let region = 4 * n + 4;
+        let north_height = north_facing.region_graph.vertices[region].center.z;
+        let south_height = south_facing.region_graph.vertices[region].center.z;
+        assert!(north_facing.snow_mask(north_height)[region]);
+        assert!(!south_facing.snow_mask(south_height)[region]);
+    }
+
+    #[test]
+    fn split_meshes_place_each_triangle_in_one_mesh() {
+        let terrain = build_grid(8);
+        let (land, water) = terrain.split_meshes();
+        assert!(!land.indices.is_empty());
+        assert!(!water.indices.is_empty());
+
+        let position_key = |position: [f32; 3]| [position[0].to_bits(), position[1].to_bits(), position[2].to_bits()];
+        let mesh_triangles = |mesh: &Mesh| -> Vec<[[u32; 3]; 3]> {
+            mesh.indices
+                .chunks(3)
+                .map(|triangle| {
+                    [position_key(mesh.positions[triangle[0] as usize]),
+                     position_key(mesh.positions[triangle[1] as usize]),
+                     position_key(mesh.positions[triangle[2] as usize])]
+                })
+                .collect()
+        };
+        let land_triangles = mesh_triangles(&land);
+        let water_triangles = mesh_triangles(&water);
+
+        let triangles = terrain.triangles();
+        assert_eq!(land_triangles.len() + water_triangles.len(), triangles.len());
+        for triangle in triangles.iter() {
+            let vertex_key = |v: usize| {
+                let position = terrain.terrain_graph.vertices[v].position;
+                position_key([position.x as f32, position.y as f32, position.z as f32])
+            };
+            let key = [vertex_key(triangle[0]), vertex_key(triangle[1]), vertex_key(triangle[2])];
+            let count = land_triangles.iter().chain(water_triangles.iter()).filter(|&&other| other == key).count();
+            assert_eq!(count, 1);
+        }
+    }
+
+    #[test]
+    fn mesh_uvs_line_up_with_positions() {
+        let terrain = build_grid(8);
+        let (min, max) = terrain.site_bounds();
+        let (land, water) = terrain.split_meshes();
+
+        for mesh in [land, water].iter() {
+            assert_eq!(mesh.uvs.len(), mesh.positions.len());
+            for (uv, position) in mesh.uvs.iter().zip(mesh.positions.iter()) {
+                let expected = [((position[0] as f64 - min.0) / (max.0 - min.0)).clamp(0.0, 1.0),
+                                ((position[1] as f64 - min.1) / (max.1 - min.1)).clamp(0.0, 1.0)];
+                assert!((uv[0] as f64 - expected[0]).abs() < 1e-5);
+                assert!((uv[1] as f64 - expected[1]).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn morph_heights_ends_at_each_seeds_heights() {
+        let build = |seed| {
+            VoronoiTerrain::builder()
+                .set_seed(seed)
+                .set_sites(grid_sites(6))
+                .build()
+                .unwrap()
+        };
+        let (terrain_a, terrain_b) = (build(3), build(11));
+
+        for &(t, expected) in [(0.0, &terrain_a), (1.0, &terrain_b)].iter() {
+            let mut terrain = build(3);
+            terrain.morph_heights(3, 11, t);
+            for vertex in terrain.terrain_graph.vertices.iter() {
+                let position = vertex.position;
+                let expected_position = nearest_vertex(expected, (position.x, position.y)).position;
+                assert_eq!((expected_position.x, expected_position.y), (position.x, position.y));
+                assert!((position.z - expected_position.z).abs() < 1e-12);
+            }
+        }
+    }
+
+    #[test]
+    fn land_centroid_of_a_half_submerged_region_lies_on_land() {
+        let mut terrain = build_grid_with_heights(8, |_, _| -0.5);
+        let water_height = terrain.water_height();
+
+        // Tilt a region so that its eastern half rises out of the water.
+        let region = 2 * 8 + 2;
+        let polygon = terrain.region_polygon(region);
+        let centroid = polygon_centroid(&polygon);
+        for v in terrain.region_graph.vertices[region].vertices.clone() {
+            let x = terrain.terrain_graph.vertices[v].position.x;
+            terrain.set_vertex_height(v, water_height + x - centroid.0);
+        }
+
+        let land_centroid = terrain.land_centroid(region).unwrap();
+        assert!(land_centroid.x > centroid.0);
+        assert!(point_in_polygon((land_centroid.x, land_centroid.y), &polygon));
+        assert!(land_centroid.z >= water_height);
+
+        assert!(terrain.land_centroid(5 * 8 + 5).is_none());
+    }
+
+    #[test]
+    fn ridge_lines_trace_a_single_ridge() {
+        let terrain = VoronoiTerrain::builder()
+            .set_seed(7)
+            .set_sites(grid_sites(10))
+            .set_vertex_post_process(|_, vertex| vertex.position.z = 0.8 - 0.3 * (vertex.position.y - 4.5).abs())
+            .build()
+            .unwrap();
+
+        let ridges = terrain.ridge_lines();
+        assert_eq!(ridges.len(), 1);
+        let (first, last) = (ridges[0][0], ridges[0][ridges[0].len() - 1]);
+        let vertices = &terrain.terrain_graph.vertices;
+        assert!((vertices[first].position.x - vertices[last].position.x).abs() > 6.0);
+        for &v in ridges[0].iter() {
+            let position = terrain.terrain_graph.vertices[v].position;
+            assert!((position.y - 4.5).abs() < 1.0);
+            for &side in [-1.0, 1.0].iter() {
+                if let Some(height) = terrain.height_at(position.x, position.y + side) {
+                    assert!(height < position.z);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn ridge_lines_of_a_ramp_are_empty() {
+        let terrain = VoronoiTerrain::builder()
+            .set_seed(7)
+            .set_sites(grid_sites(10))
+            .set_vertex_post_process(|_, vertex| vertex.position.z = 0.1 * vertex.position.x - 0.5)
+            .build()
+            .unwrap();
+
+        assert!(terrain.ridge_lines().is_empty());
+    }
+
+    #[test]
+    fn raycast_hits_flat_terrain_at_the_expected_point() {
+        let terrain = build_grid_with_heights(8, |_, _| 0.0);
+
+        // Aim diagonally down at (4, 3) from above (2, 2).
+        let ray = Ray {
+            origin: Point3::new(2.0, 2.0, 1.0),
+            direction: Vector3::new(2.0, 1.0, -1.0),
+        };
+
+        let (region, point) = terrain.raycast(&ray).unwrap();
+        assert!((point - Point3::new(4.0, 3.0, 0.0)).magnitude() < 1e-6);
+        assert_eq!(terrain.region_of(4.0, 3.0), Some(region));
+
+        let vertical = Ray {
+            origin: Point3::new(4.0, 3.0, 1.0),
+            direction: Vector3::new(0.0, 0.0, -1.0),
+        };
+        let (_, point) = terrain.raycast(&vertical).unwrap();
+        assert!((point - Point3::new(4.0, 3.0, 0.0)).magnitude() < 1e-9);
+    }
+
+    #[test]
+    fn sites_keep_the_boundary_margin() {
+        let margin = 1.5;
+        let terrain = VoronoiTerrain::builder()
+            .set_seed(7)
+            .set_sites(grid_sites(8))
+            .set_boundary_margin(margin)
+            .build()
+            .unwrap();
+
+        let (min, max) = point_bounds(&grid_sites(8));
+        let sites = &terrain.site_of_region;
+        assert!(!sites.is_empty());
+        assert!(sites.iter().all(|&(x, y)| {
+            x - min.0 >= margin && max.0 - x >= margin && y - min.1 >= margin && max.1 - y >= margin
+        }));
+    }
+
+    #[test]
+    fn ruggedness_of_flat_ground_is_below_mountains() {
+        // Flat to the west and alternating peaks and pits to the east.
+        let terrain = build_grid_with_heights(10, |column, row| {
+            if column < 5 { 0.0 } else if (column + row) % 2 == 0 { 0.8 } else { -0.8 }
+        });
+
+        let flat = terrain.ruggedness(2.0, 4.5, 1.5);
+        let mountainous = terrain.ruggedness(7.5, 4.5, 1.5);
+        assert!(flat < 1e-9);
+        assert!(mountainous > flat);
+    }
+
+    /** A round island centered on a 10 by 10 grid, land within a radius of about 3. */
+    fn island(column: usize, row: usize) -> f64 {
+        let (dx, dy) = (column as f64 - 4.5, row as f64 - 4.5);
+        0.8 * (-(dx * dx + dy * dy) / 8.0).exp() - 0.4
+    }
+
+    #[test]
+    fn coastline_medial_axis_lies_on_land() {
+        let terrain = build_grid_with_heights(10, island);
+        let water_height = terrain.water_height();
+
+        let axis = terrain.coastline_medial_axis();
+        assert!(!axis.is_empty());
+        for &(a, b) in axis.iter() {
+            let middle = a.midpoint(b);
+            for point in [a, b, middle].iter() {
+                assert!(terrain.height_at(point.x, point.y).unwrap() >= water_height);
+            }
+        }
+    }
+
+    #[test]
+    fn lod_levels_have_fewer_regions_in_similar_bounds() {
+        let terrain = build_grid(12);
+        let (min, max) = terrain.site_bounds();
+
+        let mut previous = terrain.region_graph.vertices.len();
+        for level in 1..4 {
+            let lod = terrain.lod(level);
+            let regions = lod.region_graph.vertices.len();
+            assert!(regions < previous);
+            previous = regions;
+
+            let (lod_min, lod_max) = lod.site_bounds();
+            assert!(lod_min.0 >= min.0 && lod_min.1 >= min.1 && lod_max.0 <= max.0 && lod_max.1 <= max.1);
+            assert!(lod_min.0 - min.0 < 1.5 && lod_min.1 - min.1 < 1.5);
+            assert!(max.0 - lod_max.0 < 1.5 && max.1 - lod_max.1 < 1.5);
+        }
+    }
+
+    #[test]
+    fn region_distance_matches_a_hand_computed_distance() {
+        let terrain = build_grid(6);
+        let (a, b) = (terrain.region_graph.vertices[7].center, terrain.region_graph.vertices[14].center);
+
+        let (dx, dy, dz) = (b.x - a.x, b.y - a.y, b.z - a.z);
+        let expected = (dx * dx + dy * dy + dz * dz).sqrt();
+        assert!((terrain.region_distance(7, 14) - expected).abs() < 1e-12);
+        assert_eq!(terrain.region_distance(7, 14), terrain.region_distance(14, 7));
+        assert_eq!(terrain.region_distance(7, 7), 0.0);
+    }
+
+    #[test]
+    fn drainage_pits_report_a_closed_basin() {
+        let n = 10;
+        let cone = |column: usize, row: usize| {
+            let (dx, dy) = (column as f64 - 4.0, row as f64 - 4.0);
+            0.6 - 0.05 * (dx * dx + dy * dy).sqrt()
+        };
+        assert!(build_grid_with_heights(n, cone).drainage_pits().is_empty());
+
+        let terrain = build_grid_with_heights(n, |column, row| {
+            if column == 4 && row == 4 { 0.1 } else { cone(column, row) }
+        });
+        assert!(terrain.drainage_pits().contains(&(4 * n + 4)));
+    }
+
+    #[test]
+    fn visibility_polygon_on_flat_terrain_is_a_full_circle() {
+        let terrain = build_grid_with_heights(12, |_, _| 0.0);
+        let region = 6 * 12 + 6;
+        let center = terrain.region_graph.vertices[region].center;
+
+        let outline = terrain.visibility_polygon(region, 3.0);
+        assert_eq!(outline.len(), 64);
+        for &point in outline.iter() {
+            assert!((distance2(point, (center.x, center.y)).sqrt() - 3.0).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn injected_heights_appear_at_their_sites() {
+        let n = 10;
+        let height = |x: f64, y: f64| 0.1 * x - 0.05 * y - 0.2;
+        let sites: Vec<(f64, f64, f64)> = grid_sites(n)
+            .into_iter()
+            .map(|(x, y)| (x, y, height(x, y)))
+            .collect();
+        let terrain = VoronoiTerrain::builder()
+            .set_seed(7)
+            .set_sites_with_heights(sites.clone())
+            .build()
+            .unwrap();
+
+        for &(x, y, z) in sites.iter() {
+            let (column, row) = (x.round() as usize, y.round() as usize);
+            if column == 0 || row == 0 || column == n - 1 || row == n - 1 {
+                continue;
+            }
+            assert!((terrain.height_at(x, y).unwrap() - z).abs() < 0.05);
+        }
+    }
+
+    #[test]
+    fn complexity_of_flat_terrain_is_below_noisy_terrain() {
+        let flat = VoronoiTerrain::builder()
+            .set_seed(7)
+            .set_sites(grid_sites(8))
+            .set_flat(true)
+            .build()
+            .unwrap();
+        let noisy = build_grid(8);
+
+        assert!(flat.complexity() < 1e-9);
+        assert!(noisy.complexity() > flat.complexity() + 1.0);
+    }
+
+    #[test]
+    fn minimap_has_the_requested_size_and_varied_colors() {
+        let terrain = build_grid(8);
+
+        let minimap = terrain.minimap(32);
+        assert_eq!(minimap.len(), 32 * 32);
+        assert!(minimap.iter().any(|&color| color != minimap[0]));
+        assert!(minimap.iter().all(|color| color[3] == 255));
+    }
+
+    #[test]
+    fn river_segments_widen_downstream() {
+        let terrain = build_grid(8);
+        let segments = terrain.river_segments();
+        let width_from: HashMap<usize, f64> = segments.iter().map(|&(from, _, width)| (from, width)).collect();
+
+        let mut checked = 0;
+        for &(_, to, width) in segments.iter() {
+            if let Some(&downstream_width) = width_from.get(&to) {
+                assert!(downstream_width > width);
+                checked += 1;
+            }
+        }
+        assert!(checked > 0);
+    }
+
+    #[test]
+    fn mask_polygon_keeps_only_regions_inside_it() {
+        let triangle = vec![(1.0, 1.0), (8.0, 1.0), (4.5, 8.0)];
+        let terrain = VoronoiTerrain::builder()
+            .set_seed(7)
+            .set_sites(grid_sites(10))
+            .set_mask_polygon(triangle.clone())
+            .build()
+            .unwrap();
+
+        let expected = build_grid(10).region_graph.vertices
+            .iter()
+            .filter(|region| point_in_polygon((region.center.x, region.center.y), &triangle))
+            .count();
+        assert!(expected > 0);
+        assert_eq!(terrain.region_graph.vertices.len(), expected);
+        for region in terrain.region_graph.vertices.iter() {
+            assert!(point_in_polygon((region.center.x, region.center.y), &triangle));
+        }
+    }
+
+    #[test]
+    fn land_path_stays_on_its_landmass() {
+        let n = 10;
+        let terrain = build_grid_with_heights(n, |column, _| if (4..=6).contains(&column) { -0.5 } else { 0.5 });
+        let water_height = terrain.water_height();
+        let (west, east) = (4 * n + 1, 4 * n + 8);
+
+        assert_eq!(terrain.land_path(west, east), None);
+
+        let path = terrain.land_path(west, 7 * n + 2).unwrap();
+        assert_eq!(path[0], west);
+        assert_eq!(path[path.len() - 1], 7 * n + 2);
+        for &region in path.iter() {
+            assert!(terrain.region_graph.vertices[region].center.z >= water_height);
+        }
+    }
+
+    #[test]
+    fn harbor_candidates_rank_a_bay_above_a_straight_coast() {
+        // Land to the west of a straight north-south coast, with a bay cut into it.
+        let n = 12;
+        let terrain = build_grid_with_heights(n, |column, row| {
+            let bay = column >= 3 && (5..=7).contains(&row);
+            if column >= 6 || bay { -0.5 } else { 0.5 }
+        });
+
+        let candidates = terrain.harbor_candidates();
+        let rank = |region: usize| candidates.iter().position(|&candidate| candidate == region).unwrap();
+        let (bay, straight) = (8 * n + 4, 2 * n + 5);
+        assert!(rank(bay) < rank(straight));
+
+        let (column, row) = (candidates[0] % n, candidates[0] / n);
+        assert!((2..=5).contains(&column) && (4..=8).contains(&row));
+    }
+
+    #[test]
+    fn elevation_bands_cover_the_footprint_without_overlap() {
+        let terrain = build_grid(8);
+        let step = 0.2;
+
+        let bands = terrain.elevation_bands(step);
+        for window in bands.windows(2) {
+            assert!(window[0].0 < window[1].0);
+        }
+
+        // The bands cover the same area as the triangles they are cut from.
+        let vertices = &terrain.terrain_graph.vertices;
+        let footprint: f64 = terrain.triangles()
+            .iter()
+            .map(|triangle| {
+                let polygon: Vec<(f64, f64)> = triangle.iter()
+                    .map(|&i| (vertices[i].position.x, vertices[i].position.y))
+                    .collect();
+                polygon_area(&polygon)
+            })
+            .sum();
+        let covered: f64 = bands.iter()
+            .flat_map(|(_, polygons)| polygons.iter())
+            .map(|polygon| polygon_area(polygon))
+            .sum();
+        assert!((covered - footprint).abs() < footprint * 1e-9);
+
+        // Each point of the terrain lies in exactly one band.
+        for i in 0..20 {
+            for j in 0..20 {
+                let point = (0.3 + i as f64 * 0.33, 0.2 + j as f64 * 0.34);
+                let containing = bands.iter()
+                    .flat_map(|(_, polygons)| polygons.iter())
+                    .filter(|polygon| point_in_polygon(point, polygon))
+                    .count();
+                assert_eq!(containing, 1);
+            }
+        }
+    }
+
+    #[test]
+    fn site_of_region_is_the_input_site() {
+        let sites = grid_sites(6);
+        let terrain = VoronoiTerrain::builder()
+            .set_seed(7)
+            .set_sites(sites.clone())
+            .build()
+            .unwrap();
+
+        assert_eq!(terrain.site_of_region, sites);
+        for (region, &(x, y)) in terrain.site_of_region.iter().enumerate() {
+            assert_eq!(terrain.region_of(x, y), Some(region));
+        }
+    }
+
+    #[test]
+    fn walkable_fraction_grows_with_the_slope_threshold() {
+        let terrain = build_grid(8);
+
+        assert_eq!(terrain.walkable_fraction(0.0), 0.0);
+        let mut previous = 0.0;
+        for &max_slope in [0.05, 0.1, 0.2, 0.4, 0.8, 1.6].iter() {
+            let fraction = terrain.walkable_fraction(max_slope);
+            assert!(fraction >= previous);
+            previous = fraction;
+        }
+        assert!(previous > 0.0);
+        assert_eq!(terrain.walkable_fraction(f64::INFINITY), 1.0);
+    }
+
+    /** Counts the connected landmasses of a terrain. */
+    fn landmasses(terrain: &VoronoiTerrain) -> usize {
+        let regions = &terrain.region_graph.vertices;
+        let water_height = terrain.water_height();
+        let is_land = |region: usize| regions[region].center.z >= water_height;
+        let adjacency = terrain.local_adjacency();
+
+        let mut seen: Vec<bool> = vec![false; regions.len()];
+        let mut count = 0;
+        for start in 0..regions.len() {
+            if seen[start] || !is_land(start) {
+                continue;
+            }
+            count += 1;
+            seen[start] = true;
+            let mut stack = vec![start];
+            while let Some(region) = stack.pop() {
+                for &neighbour in adjacency[region].iter() {
+                    if !seen[neighbour] && is_land(neighbour) {
+                        seen[neighbour] = true;
+                        stack.push(neighbour);
+                    }
+                }
+            }
+        }
+        count
+    }
+
+    #[test]
+    fn archipelago_has_more_landmasses_than_pangaea() {
+        let build = |layout| {
+            VoronoiTerrain::builder()
+                .set_seed(7)
+                .set_sites(grid_sites(24))
+                .set_continent_layout(layout)
+                .build()
+                .unwrap()
+        };
+
+        let pangaea = landmasses(&build(ContinentLayout::Pangaea));
+        let archipelago = landmasses(&build(ContinentLayout::Archipelago));
+        assert!(pangaea >= 1);
+        assert!(archipelago > pangaea);
+    }
+
+    #[test]
+    fn land_outline_of_an_island_is_one_closed_loop() {
+        let terrain = build_grid_with_heights(10, island);
+        let water_height = terrain.water_height();
+        let vertices = &terrain.terrain_graph.vertices;
+
+        let loops = terrain.land_outline();
+        assert_eq!(loops.len(), 1);
+        let outline = &loops[0];
+        assert!(outline.len() >= 3);
+
+        // Consecutive points, including the last and first, are joined by terrain edges.
+        let position = |i: usize| (vertices[i].position.x, vertices[i].position.y);
+        let edges: Vec<((f64, f64), (f64, f64))> = terrain.terrain_graph.edges
+            .iter()
+            .map(|&(a, b)| (position(a), position(b)))
+            .collect();
+        for i in 0..outline.len() {
+            let (a, b) = (outline[i], outline[(i + 1) % outline.len()]);
+            assert!(edges.iter().any(|&edge| edge == (a, b) || edge == (b, a)));
+        }
+
+        let land: Vec<(f64, f64)> = terrain.region_graph.vertices
+            .iter()
+            .filter(|region| region.center.z >= water_height)
+            .flat_map(|region| region.vertices.iter().map(|&i| position(i)))
+            .collect();
+        assert_eq!(point_bounds(outline), point_bounds(&land));
+    }
+
+    #[test]
+    fn fertility_favours_flat_warm_moist_land() {
+        // Sea to the west, a flat coastal plain, then mountains rising to the east.
+        let n = 12;
+        let terrain = build_grid_with_heights(n, |column, _| {
+            if column <= 1 {
+                -0.5
+            } else if column <= 5 {
+                0.05
+            } else {
+                0.05 + 0.15 * (column - 5) as f64
+            }
+        });
+
+        let fertility = terrain.fertility();
+        let (plain, mountain) = (5 * n + 3, 5 * n + 10);
+        assert!(fertility[plain] > fertility[mountain]);
+        assert_eq!(fertility[5 * n], 0.0);
+    }
+
+    #[test]
+    fn isthmuses_find_the_bridge_of_a_dumbbell() {
+        // Two square islands joined by a one region wide land bridge.
+        let n = 12;
+        let mut terrain = build_grid_with_heights(n, |column, row| {
+            let west = (1..=4).contains(&column) && (3..=8).contains(&row);
+            let east = (7..=10).contains(&column) && (3..=8).contains(&row);
+            let bridge = (5..=6).contains(&column) && row == 5;
+            if west || east || bridge { 0.9 } else { -0.9 }
+        });
+        assert_eq!(landmasses(&terrain), 1);
+
+        let isthmuses = terrain.isthmuses();
+        assert!(isthmuses.contains(&(5 * n + 5)) && isthmuses.contains(&(5 * n + 6)));
+
+        // Each isthmus, and nothing else, splits the landmass when flooded.
+        let regions = terrain.region_graph.vertices.len();
+        for region in 0..regions {
+            let height = terrain.region_graph.vertices[region].center.z;
+            if height < terrain.water_height() {
+                continue;
+            }
+            terrain.region_graph.vertices[region].center.z = -1.0;
+            assert_eq!(landmasses(&terrain) > 1, isthmuses.contains(&region));
+            terrain.region_graph.vertices[region].center.z = height;
+        }
+    }
+
+    #[test]
+    fn road_network_connects_hubs_over_land() {
+        let terrain = build_grid_with_heights(10, island);
+        let water_height = terrain.water_height();
+        let adjacency = terrain.region_graph.adjacency();
+        let hubs = [34, 46, 53, 65];
+
+        let roads = terrain.road_network(&hubs);
+        assert_eq!(roads.len(), hubs.len() - 1);
+
+        let mut connected: Vec<usize> = vec![roads[0][0]];
+        for road in roads.iter() {
+            assert!(connected.contains(&road[0]));
+            connected.push(road[road.len() - 1]);
+            for step in road.windows(2) {
+                assert!(adjacency[step[0]].contains(&step[1]));
+            }
+            for &region in road.iter() {
+                assert!(terrain.region_graph.vertices[region].center.z >= water_height);
+            }
+        }
+        for hub in hubs.iter() {
+            assert!(connected.contains(hub));
+        }
+    }
+
+    #[test]
+    fn rain_shadow_dries_the_lee_of_a_ridge() {
+        let n = 12;
+        let terrain = build_grid_with_heights(n, |column, _| if column == 6 { 0.9 } else { 0.1 });
+
+        let moisture = terrain.rain_shadow(Vector2::new(1.0, 0.0));
+        let (windward, leeward) = (5 * n + 3, 5 * n + 9);
+        assert!(moisture[leeward] < moisture[windward]);
+        assert!(moisture[leeward] < 0.5);
+    }
+
+    #[test]
+    fn region_screen_area_is_larger_when_nearer() {
+        let n = 10;
+        let terrain = build_grid_with_heights(n, |_, _| 0.0);
+        let view = Matrix4::<f64>::look_at(Point3::new(4.5, -3.0, 3.0),
+                                           Point3::new(4.5, 4.5, 0.0),
+                                           Vector3::new(0.0, 0.0, 1.0));
+        let projection = cgmath::perspective(cgmath::Deg(60.0), 1.0, 0.1, 100.0);
+
+        let screen_areas = terrain.region_screen_area(&view, &projection, (800, 800));
+        let areas = terrain.region_areas();
+        let (near, far) = (n + 4, 8 * n + 4);
+        assert!(screen_areas[near] > 0.0);
+        assert!(screen_areas[near] / areas[near] > screen_areas[far] / areas[far]);
+    }
+
+    #[test]
+    fn catchments_of_every_sink_partition_the_regions() {
+        let terrain = build_grid(8);
+        let water_height = terrain.water_height();
+        let regions = &terrain.region_graph.vertices;
+
+        let mut covered: Vec<usize> = vec![0; regions.len()];
+        for (sink, _) in terrain.region_flow_targets().into_iter().enumerate().filter(|&(_, target)| target.is_none()) {
+            for region in terrain.catchment(sink) {
+                covered[region] += 1;
+            }
+        }
+        assert!(covered.iter().all(|&count| count == 1));
+        assert!(regions.iter().any(|region| region.center.z >= water_height));
+    }
+
+    #[test]
+    fn region_names_depend_only_on_the_seed() {
+        let terrain = build_grid(6);
+
+        let names = terrain.region_names(11);
+        assert_eq!(names.len(), terrain.region_graph.vertices.len());
+        assert!(names.iter().all(|name| !name.is_empty()));
+        assert_eq!(names, terrain.region_names(11));
+        assert!(names != terrain.region_names(12));
+    }
+
+    #[test]
+    fn narrow_frustum_sees_only_the_regions_in_front() {
+        let terrain = build_grid_with_heights(10, |_, _| 0.0);
+        let camera_pos = Point3::new(4.5, 4.5, 20.0);
+        let view = Matrix4::<f64>::look_at(camera_pos, Point3::new(4.5, 4.5, 0.0), Vector3::new(0.0, 1.0, 0.0));
+        let projection = cgmath::perspective(cgmath::Deg(5.0), 1.0, 0.1, 100.0);
+        let frustum = Frustum::from_matrix(&(projection * view));
+
+        let visible = terrain.potentially_visible_set(camera_pos, &frustum);
+        let center = terrain.region_of(4.5, 4.5).unwrap();
+        let corner = terrain.region_of(0.5, 0.5).unwrap();
+        assert!(visible.contains(&center));
+        assert!(!visible.contains(&corner));
+        assert!(visible.len() < terrain.region_graph.vertices.len() / 4);
+    }
+
+    #[test]
+    fn adaptive_density_adds_more_sites_at_height() {
+        let coarse = build_grid(10);
+        let mut heights: Vec<f64> = coarse.region_graph.vertices.iter().map(|region| region.center.z).collect();
+        heights.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let threshold = heights[heights.len() / 2];
+
+        let dense = VoronoiTerrain::builder()
+            .set_seed(7)
+            .set_sites(grid_sites(10))
+            .set_adaptive_density(move |h| if h > threshold { 6.0 } else { 1.0 })
+            .build()
+            .unwrap();
+        assert!(dense.region_graph.vertices.len() > coarse.region_graph.vertices.len());
+
+        // The mean number of sites falling within each coarse region, split by height.
+        let (mut high, mut low) = ((0, 0), (0, 0));
+        let mut counts = vec![0; coarse.region_graph.vertices.len()];
+        for &(x, y) in dense.site_of_region.iter() {
+            counts[coarse.region_of(x, y).unwrap()] += 1;
+        }
+        for (region, &count) in counts.iter().enumerate() {
+            let total = if coarse.region_graph.vertices[region].center.z > threshold { &mut high } else { &mut low };
+            total.0 += count;
+            total.1 += 1;
+        }
+        assert!(high.0 as f64 / high.1 as f64 > 2.0 * low.0 as f64 / low.1 as f64);
+
+        let (min, max) = coarse.site_bounds();
+        assert!(dense.site_of_region.iter().all(|&(x, y)| x >= min.0 && x <= max.0 && y >= min.1 && y <= max.1));
+    }
+
+    #[test]
+    fn asc_header_matches_the_grid() {
+        let terrain = build_grid(6);
+        let mut output = Vec::new();
+        terrain.write_asc(&mut output, 12, 8).unwrap();
+
+        let text = String::from_utf8(output).unwrap();
+        let mut lines = text.lines();
+        let mut header = |key: &str| {
+            let line = lines.next().unwrap();
+            let mut parts = line.split_whitespace();
+            assert_eq!(parts.next(), Some(key));
+            parts.next().unwrap().parse::<f64>().unwrap()
+        };
+        assert_eq!(header("ncols"), 12.0);
+        assert_eq!(header("nrows"), 8.0);
+        let (min, max) = terrain.site_bounds();
+        assert_eq!(header("xllcorner"), min.0);
+        assert_eq!(header("yllcorner"), min.1);
+        let cellsize = header("cellsize");
+        assert!(12.0 * cellsize >= max.0 - min.0 - 1e-9 && 8.0 * cellsize >= max.1 - min.1 - 1e-9);
+        assert_eq!(header("NODATA_value"), -9999.0);
+
+        let rows: Vec<Vec<f64>> = lines
+            .map(|line| line.split_whitespace().map(|h| h.parse().unwrap()).collect())
+            .collect();
+        assert_eq!(rows.len(), 8);
+        assert!(rows.iter().all(|row| row.len() == 12));
+    }
+
+    #[test]
+    fn cliff_edges_follow_a_step() {
+        let flat = build_grid_with_heights(10, |_, _| 0.0);
+        assert!(flat.cliff_edges(0.1).is_empty());
+
+        let stepped = build_grid_with_heights(10, |column, _| if column < 5 { 0.0 } else { 1.0 });
+        let cliffs = stepped.cliff_edges(0.4);
+        assert!(!cliffs.is_empty());
+        for &edge in cliffs.iter() {
+            let (a, b) = stepped.region_graph.edges[edge];
+            let (xa, xb) = (stepped.site_of_region[a].0, stepped.site_of_region[b].0);
+            assert!((xa < 4.5) != (xb < 4.5));
+        }
+    }
+
+    #[test]
+    fn blending_a_seam_smooths_the_step() {
+        let mut terrain = build_grid_with_heights(10, |column, _| if column < 5 { 0.0 } else { 1.0 });
+        let (seam_x, width) = (4.5, 2.0);
+
+        // The steepest slope of the terrain edges within the sites' bounds crossing a vertical line.
+        let steepest_crossing = |terrain: &VoronoiTerrain, x: f64| {
+            let vertices = &terrain.terrain_graph.vertices;
+            terrain.terrain_graph.edges.iter()
+                .map(|&(a, b)| (vertices[a].position, vertices[b].position))
+                .filter(|&(a, b)| (a.x < x) != (b.x < x) && a.y.min(b.y) > 0.0 && a.y.max(b.y) < 9.0)
+                .map(|(a, b)| (b.z - a.z).abs() / distance2((a.x, a.y), (b.x, b.y)).sqrt())
+                .fold(0.0, f64::max)
+        };
+
+        let before = steepest_crossing(&terrain, seam_x);
+        terrain.blend_seam(seam_x, width);
+        let after = steepest_crossing(&terrain, seam_x);
+        assert!(after < before / 4.0);
+
+        // The seam no longer stands out from the slopes beside it.
+        for &x in [seam_x - 0.5, seam_x + 0.5].iter() {
+            assert!((after - steepest_crossing(&terrain, x)).abs() < 0.2);
+        }
+    }
+
+    #[test]
+    fn strip_keeps_only_the_regions_within_it() {
+        let terrain = build_grid(10);
+        let (x0, x1) = (3.0, 6.0);
+        let qualifying = terrain.region_graph.vertices.iter()
+            .filter(|region| region.center.x >= x0 && region.center.x <= x1)
+            .count();
+
+        let strip = terrain.strip(x0, x1);
+        let regions = &strip.region_graph.vertices;
+        assert!(qualifying > 0);
+        assert_eq!(regions.len(), qualifying);
+        assert_eq!(strip.site_of_region.len(), qualifying);
+        assert!(regions.iter().all(|region| region.center.x >= x0 && region.center.x <= x1));
+
+        // The renumbered graphs only refer to what the strip kept.
+        let vertex_count = strip.terrain_graph.vertices.len();
+        assert!(regions.iter().all(|region| region.vertices.iter().all(|&i| i < vertex_count)));
+        assert!(strip.region_graph.edges.iter().all(|&(a, b)| a < regions.len() && b < regions.len()));
+        assert!(strip.terrain_graph.edges.iter().all(|&(a, b)| a < vertex_count && b < vertex_count));
+    }
+
+    #[test]
+    fn region_adjacency_matches_the_region_graph() {
+        let terrain = build_grid(8);
+        let adjacency = terrain.region_graph.adjacency();
+
+        assert_eq!(terrain.region_adjacency(), &adjacency[..]);
+        for (region, neighbours) in adjacency.iter().enumerate() {
+            assert_eq!(terrain.region_neighbors(region), &neighbours[..]);
+        }
+
+        let strip = terrain.strip(2.0, 5.0);
+        assert_eq!(strip.region_adjacency(), &strip.region_graph.adjacency()[..]);
+        let coarse = terrain.lod(1);
+        assert_eq!(coarse.region_adjacency(), &coarse.region_graph.adjacency()[..]);
+    }
+
+    #[test]
+    fn invalidated_caches_follow_graph_edits() {
+        let mut terrain = build_grid(8);
+        let (a, b) = terrain.region_graph.edges[0];
+        assert!(terrain.region_neighbors(a).contains(&b));
+        let v = terrain.region_graph.vertices[a].vertices[0];
+        assert!(terrain.regions_by_vertex()[v].contains(&a));
+
+        terrain.region_graph.edges.remove(0);
+        terrain.region_graph.vertices[a].vertices.retain(|&i| i != v);
+        terrain.invalidate_caches();
+
+        assert_eq!(terrain.region_adjacency(), &terrain.region_graph.adjacency()[..]);
+        assert!(!terrain.regions_by_vertex()[v].contains(&a));
+    }
+
+    #[test]
+    fn nearest_land_from_open_ocean_is_the_closest_coast() {
+        let terrain = build_grid_with_heights(10, island);
+        let water_height = terrain.water_height();
+        let regions = &terrain.region_graph.vertices;
+        let (x, y) = (0.5, 0.5);
+        assert!(regions[terrain.region_of(x, y).unwrap()].center.z < water_height);
+
+        // The distance to each land region's boundary, approximated by points along its edges.
+        let boundary_distance = |region: usize| {
+            let polygon = terrain.region_polygon(region);
+            (0..polygon.len())
+                .flat_map(|i| {
+                    let (a, b) = (polygon[i], polygon[(i + 1) % polygon.len()]);
+                    (0..=100).map(move |t| {
+                        let t = t as f64 / 100.0;
+                        distance2((x, y), (a.0 + t * (b.0 - a.0), a.1 + t * (b.1 - a.1)))
+                    })
+                })
+                .fold(f64::INFINITY, f64::min)
+        };
+        let expected = (0..regions.len())
+            .filter(|&region| regions[region].center.z >= water_height)
+            .min_by(|&a, &b| boundary_distance(a).partial_cmp(&boundary_distance(b)).unwrap())
+            .unwrap();
+
+        let nearest = terrain.nearest_land(x, y).unwrap();
+        assert_eq!(nearest, expected);
+        assert!(terrain.region_neighbors(nearest).iter().any(|&neighbour| regions[neighbour].center.z < water_height));
+    }
+
+    #[test]
+    fn ridge_top_is_more_exposed_than_the_valley_behind_it() {
+        let n = 10;
+        let terrain = build_grid_with_heights(n, |column, _| match column {
+            0..=6 => 0.15 * column as f64,
+            7 => 0.5,
+            _ => 0.2,
+        });
+
+        let exposure = terrain.wind_exposure(Vector2::new(1.0, 0.0));
+        let (ridge, valley) = (4 * n + 6, 4 * n + 8);
+        assert!(exposure[ridge] > 0.0);
+        assert_eq!(exposure[valley], 0.0);
+        assert!(exposure[ridge] > exposure[valley]);
+    }
+
+    #[test]
+    fn larger_isochrone_budget_reaches_a_superset() {
+        let terrain = build_grid(10);
+        let start = 4 * 10 + 4;
+        let cost = |region: usize| 1.0 + terrain.region_graph.vertices[region].center.z.abs();
+
+        let mut previous = terrain.isochrone(start, 0.0, cost);
+        assert_eq!(previous, vec![start]);
+        for &budget in [1.5, 3.0, 6.0].iter() {
+            let reached = terrain.isochrone(start, budget, cost);
+            assert!(reached.len() > previous.len());
+            assert!(previous.iter().all(|region| reached.binary_search(region).is_ok()));
+            previous = reached;
+        }
+    }
+
+    #[test]
+    fn watershed_divides_trace_the_ridge_between_basins() {
+        let n = 10;
+        // Two valleys along columns 2 and 7 which each slope down to a single sink in row 4.
+        let terrain = build_grid_with_heights(n, |column, row| {
+            let across = (column as f64 - 2.0).abs().min((column as f64 - 7.0).abs());
+            0.2 * across + 0.05 * (row as f64 - 4.0).abs()
+        });
+
+        let mut sinks = terrain.watersheds();
+        sinks.sort();
+        sinks.dedup();
+        assert_eq!(sinks.len(), 2);
+
+        let divides = terrain.watershed_divides();
+        assert!(divides.len() >= n);
+        for &edge in divides.iter() {
+            let (a, b) = terrain.region_graph.edges[edge];
+            let (xa, xb) = (terrain.site_of_region[a].0, terrain.site_of_region[b].0);
+            assert!((xa < 4.5) != (xb < 4.5));
+        }
+    }
+
+    #[test]
+    fn colorize_reaches_the_mesh_colors() {
+        let mut terrain = build_grid_with_heights(10, island);
+        let (land, water) = terrain.split_meshes();
+        assert!(land.colors.iter().chain(water.colors.iter()).all(|&color| color == [1.0, 1.0, 1.0, 1.0]));
+
+        let red = [1.0, 0.0, 0.0, 1.0];
+        terrain.colorize(|_| red);
+        let (land, water) = terrain.split_meshes();
+        for mesh in [land, water].iter() {
+            assert!(!mesh.colors.is_empty());
+            assert_eq!(mesh.colors.len(), mesh.positions.len());
+            assert!(mesh.colors.iter().all(|&color| color == red));
+        }
+    }
+
+    #[test]
+    fn colorized_red_reaches_the_exports() {
+        let mut terrain = build_grid_with_heights(6, island);
+        terrain.colorize(|_| [1.0, 0.0, 0.0, 1.0]);
+        let mesh = terrain.triangulated_mesh();
+        let count = mesh.positions.len();
+        assert!(count > 0);
+        assert!(mesh.colors.iter().all(|&color| color == [1.0, 0.0, 0.0, 1.0]));
+
+        let mut obj: Vec<u8> = Vec::new();
+        mesh.write_obj(&mut obj).unwrap();
+        let obj = String::from_utf8(obj).unwrap();
+        let colors: Vec<Vec<&str>> = obj.lines()
+            .filter(|line| line.starts_with("v "))
+            .map(|line| line.split(' ').skip(4).collect())
+            .collect();
+        assert_eq!(colors.len(), count);
+        assert!(colors.iter().all(|color| *color == vec!["1", "0", "0"]));
+
+        let mut ply: Vec<u8> = Vec::new();
+        mesh.write_ply(&mut ply).unwrap();
+        let ply = String::from_utf8(ply).unwrap();
+        let vertices: Vec<&str> = ply.lines()
+            .skip_while(|&line| line != "end_header")
+            .skip(1)
+            .take(count)
+            .collect();
+        assert_eq!(vertices.len(), count);
+        assert!(vertices.iter().all(|line| line.ends_with(" 255 0 0 255")));
+
+        let mut glb: Vec<u8> = Vec::new();
+        mesh.write_gltf(&mut glb).unwrap();
+        let word = |offset: usize| u32::from_le_bytes([glb[offset], glb[offset + 1], glb[offset + 2], glb[offset + 3]]);
+        assert_eq!(&glb[0..4], b"glTF");
+        assert_eq!(word(8) as usize, glb.len());
+        let json_length = word(12) as usize;
+        let json = String::from_utf8(glb[20..20 + json_length].to_vec()).unwrap();
+        assert!(json.contains("\"COLOR_0\":2"));
+
+        // The colors follow the positions and normals in the binary chunk.
+        let colors = 20 + json_length + 8 + 2 * 12 * count;
+        for i in 0..4 * count {
+            let channel = f32::from_bits(word(colors + 4 * i));
+            assert_eq!(channel, if i % 4 == 1 || i % 4 == 2 { 0.0 } else { 1.0 });
+        }
+    }
+
+    #[test]
+    fn site_samplers_are_deterministic_and_within_bounds() {
+        let bounds = ((1.0, 2.0), (9.0, 6.0));
+        let samplers = [SiteSampler::PoissonDisk { bounds, density: 2.0 },
+                        SiteSampler::UniformRandom { bounds, density: 2.0 },
+                        SiteSampler::JitteredGrid { bounds, density: 2.0 }];
+
+        for sampler in samplers.iter() {
+            let sites = sampler.sample(3);
+            assert!(sites.len() > 20);
+            assert_eq!(sites, sampler.sample(3));
+            assert!(sites != sampler.sample(4));
+
+            let ((x0, y0), (x1, y1)) = bounds;
+            assert!(sites.iter().all(|&(x, y)| (x0..=x1).contains(&x) && (y0..=y1).contains(&y)));
+        }
+    }
+
+    #[test]
+    fn poisson_disk_sites_keep_the_minimum_distance() {
+        let density = 4.0;
+        let mut rng = XorShiftRng::from_seed([1, 2, 3, 4]);
+        let sites = poisson_disk_sites(&mut rng, ((0.0, 0.0), (6.0, 6.0)), density);
+        assert!(sites.len() > 50);
+
+        let radius = (0.7 / density).sqrt();
+        for i in 0..sites.len() {
+            for j in (i + 1)..sites.len() {
+                assert!(distance2(sites[i], sites[j]) >= radius * radius);
+            }
+        }
+    }
+
+    #[test]
+    fn generated_sites_keep_the_boundary_margin() {
+        let ((x0, y0), (x1, y1)) = ((0.0, 0.0), (8.0, 8.0));
+        let margin = 1.5;
+        let terrain = VoronoiTerrain::builder()
+            .set_seed(5)
+            .set_site_sampler(SiteSampler::UniformRandom { bounds: ((x0, y0), (x1, y1)), density: 3.0 })
+            .set_boundary_margin(margin)
+            .build()
+            .unwrap();
+
+        let sites = &terrain.site_of_region;
+        assert!(!sites.is_empty());
+        assert!(sites.iter().all(|&(x, y)| {
+            x - x0 >= margin && x1 - x >= margin && y - y0 >= margin && y1 - y >= margin
+        }));
+    }
 }
@@ -1,43 +1,82 @@
 extern crate cgmath;
 extern crate gl;
 extern crate glutin;
+extern crate lib;
 extern crate libc;
 
 mod shader;
 
-use cgmath::{Deg, Matrix, Matrix4, One, vec3, perspective};
+use cgmath::{Deg, Matrix4, One, Point3, Quaternion, Rotation, Rotation3, Vector3};
 use glutin::GlContext;
+use lib::camera::Camera;
 use shader::Shader;
-use std::ffi::CStr;
 
-macro_rules! c_str {
-    ($literal:expr) => {
-        CStr::from_bytes_with_nul_unchecked(concat!($literal, "\0").as_bytes())
-    }
-}
+// The rotation of the camera per pixel dragged.
+const ORBIT_DEGREES_PER_PIXEL: f32 = 0.25;
 
 struct State<'a> {
     running: bool,
 
     //world: world::World,
     gl_window: &'a glutin::GlWindow,
+    camera: Camera,
+
+    // The last cursor position, if the left mouse button is held to orbit the camera.
+    drag_position: Option<(f64, f64)>,
+    cursor_position: (f64, f64),
 }
 
 impl<'a> State<'a> {
-    fn new(gl_window: &'a glutin::GlWindow) -> State<'a> {
+    fn new(gl_window: &'a glutin::GlWindow, camera: Camera) -> State<'a> {
         State {
             running: true,
             gl_window: gl_window,
+            camera: camera,
+            drag_position: None,
+            cursor_position: (0.0, 0.0),
         }
     }
 }
 
+/**
+ * The track-ball rotation for a mouse drag, horizontal drags turn around the camera's up axis and
+ * vertical drags around its right axis.
+ */
+fn drag_rotation(camera: &Camera, dx: f64, dy: f64) -> Quaternion<f32> {
+    let up = camera.orientation.rotate_vector(Vector3::<f32>::unit_y());
+    let right = camera.orientation.rotate_vector(Vector3::<f32>::unit_x());
+    Quaternion::from_axis_angle(up, Deg(-dx as f32 * ORBIT_DEGREES_PER_PIXEL)) *
+    Quaternion::from_axis_angle(right, Deg(-dy as f32 * ORBIT_DEGREES_PER_PIXEL))
+}
+
 fn handle_event(event: glutin::Event, state: &mut State) {
     match event {
         glutin::Event::WindowEvent { event, .. } => {
             match event {
                 glutin::WindowEvent::Closed => state.running = false,
-                glutin::WindowEvent::Resized(w, h) => state.gl_window.resize(w, h),
+                glutin::WindowEvent::Resized(w, h) => {
+                    state.gl_window.resize(w, h);
+                    if w > 0 && h > 0 {
+                        state.camera.aspect = w as f32 / h as f32;
+                        unsafe {
+                            gl::Viewport(0, 0, w as i32, h as i32);
+                        }
+                    }
+                },
+                glutin::WindowEvent::MouseInput { state: button_state, button: glutin::MouseButton::Left, .. } => {
+                    state.drag_position = match button_state {
+                        glutin::ElementState::Pressed => Some(state.cursor_position),
+                        glutin::ElementState::Released => None,
+                    };
+                },
+                glutin::WindowEvent::MouseMoved { position, .. } => {
+                    if let Some((x, y)) = state.drag_position {
+                        let rotation = drag_rotation(&state.camera, position.0 - x, position.1 - y);
+                        state.camera.orbit(rotation);
+                        state.drag_position = Some(position);
+                    }
+                    state.cursor_position = position;
+                },
                 _ => (),
             }
         }
@@ -67,46 +106,26 @@ fn main() {
         gl::Enable(gl::CULL_FACE);
     }
 
-    let mut shader: Shader = Shader { id: 0 };
-    unsafe {
-        shader = Shader::create("data/shader/vs.vert", "data/shader/fs.frag");
+    let camera = Camera::new(Point3::new(0.0, 0.0, 5.0), Quaternion::one(), Deg(80.0), 1024.0 / 780.0);
 
+    let mut shader: Shader = unsafe { Shader::create("data/shader/vs.vert", "data/shader/fs.frag") };
+    unsafe {
         gl::UseProgram(shader.id);
 
-        // TODO(orglofch): Set aspect ratio appropriately.
-        let projection: Matrix4<f32> = perspective(Deg(80.0), 1.0, 0.1, 1000.0);
-        gl::UniformMatrix4fv(gl::GetUniformLocation(shader.id, c_str!("projection").as_ptr()),
-                             1,
-                             gl::FALSE,
-                             projection.as_ptr());
-        let view = Matrix4::<f32>::from_translation(vec3(0.0, 0.0, -5.0));
-        gl::UniformMatrix4fv(gl::GetUniformLocation(shader.id, c_str!("view").as_ptr()),
-                             1,
-                             gl::FALSE,
-                             view.as_ptr());
+        shader.set_mat4("model", &Matrix4::<f32>::one());
     }
 
-    // TODO(orglofch): Use quaternions and add track-ball rotation.
-    let mut model_mat = Matrix4::<f32>::one();
-    let mut angle = 0.0;
-
-    let mut state = State::new(&gl_window);
+    let mut state = State::new(&gl_window, camera);
     while state.running {
         events_loop.poll_events(|event| handle_event(event, &mut state));
 
-        // TODO(orglofch): Why doesn't multi assign work?
-        model_mat = Matrix4::<f32>::from_angle_y(Deg(angle));
-
-        angle += 0.4;
-
         unsafe {
             gl::Clear(gl::COLOR_BUFFER_BIT);
             gl::Clear(gl::DEPTH_BUFFER_BIT);
 
-            gl::UniformMatrix4fv(gl::GetUniformLocation(shader.id, c_str!("model").as_ptr()),
-                                 1,
-                                 gl::FALSE,
-                                 model_mat.as_ptr());
+            // Unchanged matrices are skipped by the shader so these are only uploaded on change.
+            shader.set_mat4("projection", &state.camera.projection_matrix());
+            shader.set_mat4("view", &state.camera.view_matrix());
         }
 
         state.gl_window.swap_buffers().unwrap();
@@ -1,6 +1,8 @@
 extern crate gl;
 
+use cgmath::{Matrix, Matrix4, Vector3};
 use gl::types::*;
+use std::collections::HashMap;
 use std::ffi::CString;
 use std::fs::File;
 use std::io::Read;
@@ -14,8 +16,45 @@ use std::thread;
 static VERTEX_DEFINE: &'static str = "#define VERTEX \n";
 static FRAGMENT_DEFINE: &'static str = "#define FRAGMENT \n";
 
+/** The last value uploaded to a uniform. */
+#[derive(PartialEq)]
+enum Uniform {
+    Mat4(Matrix4<f32>),
+    Vec3(Vector3<f32>),
+}
+
+/** The GL calls uploading uniforms, behind a trait so that uploads can be observed in tests. */
+trait UniformApi {
+    unsafe fn uniform_location(&self, program: GLuint, name: &str) -> GLint;
+
+    unsafe fn uniform_matrix4(&self, location: GLint, value: &Matrix4<f32>);
+
+    unsafe fn uniform_vec3(&self, location: GLint, value: &Vector3<f32>);
+}
+
+/** Uploads uniforms through the loaded GL functions. */
+struct Gl;
+
+impl UniformApi for Gl {
+    unsafe fn uniform_location(&self, program: GLuint, name: &str) -> GLint {
+        let name_c_str = CString::new(name.as_bytes()).unwrap();
+        gl::GetUniformLocation(program, name_c_str.as_ptr())
+    }
+
+    unsafe fn uniform_matrix4(&self, location: GLint, value: &Matrix4<f32>) {
+        gl::UniformMatrix4fv(location, 1, gl::FALSE, value.as_ptr());
+    }
+
+    unsafe fn uniform_vec3(&self, location: GLint, value: &Vector3<f32>) {
+        gl::Uniform3f(location, value.x, value.y, value.z);
+    }
+}
+
 pub struct Shader {
     pub id: u32,
+
+    // The last value uploaded to each uniform by name, to skip redundant uploads.
+    uniforms: HashMap<String, Uniform>,
 }
 
 impl Shader {
@@ -41,7 +80,10 @@ impl Shader {
 
         let program = Shader::compile_and_link(&vs_src, &fs_src);
 
-        Shader { id: program }
+        Shader {
+            id: program,
+            uniforms: HashMap::new(),
+        }
     }
 
     /**
@@ -66,7 +108,49 @@ impl Shader {
 
         let program = Shader::compile_and_link(&vs_src, &fs_src);
 
-        Shader { id: program }
+        Shader {
+            id: program,
+            uniforms: HashMap::new(),
+        }
+    }
+
+    /**
+     * Sets a matrix uniform, skipping the upload if it's unchanged since it was last set.
+     *
+     * Requires the shader to be the current program.
+     */
+    pub unsafe fn set_mat4(&mut self, name: &str, value: &Matrix4<f32>) {
+        self.set_mat4_with(&Gl, name, value);
+    }
+
+    unsafe fn set_mat4_with<A: UniformApi>(&mut self, api: &A, name: &str, value: &Matrix4<f32>) {
+        if self.update_uniform(name, Uniform::Mat4(*value)) {
+            api.uniform_matrix4(api.uniform_location(self.id, name), value);
+        }
+    }
+
+    /**
+     * Sets a vector uniform, skipping the upload if it's unchanged since it was last set.
+     *
+     * Requires the shader to be the current program.
+     */
+    pub unsafe fn set_vec3(&mut self, name: &str, value: &Vector3<f32>) {
+        self.set_vec3_with(&Gl, name, value);
+    }
+
+    unsafe fn set_vec3_with<A: UniformApi>(&mut self, api: &A, name: &str, value: &Vector3<f32>) {
+        if self.update_uniform(name, Uniform::Vec3(*value)) {
+            api.uniform_vec3(api.uniform_location(self.id, name), value);
+        }
+    }
+
+    /** Records the latest value of a uniform, returning whether it changed. */
+    fn update_uniform(&mut self, name: &str, value: Uniform) -> bool {
+        if self.uniforms.get(name) == Some(&value) {
+            return false;
+        }
+        self.uniforms.insert(name.to_owned(), value);
+        true
     }
 
     /** Compile and link a vertex and fragment shader into a single program. */
@@ -149,3 +233,83 @@ impl Shader {
         return String::from_utf8(buffer).expect("Shader info-log couldn't be parsed as utf8");
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{Deg, One};
+    use std::cell::Cell;
+
+    /** Counts uploads instead of calling GL. */
+    struct CountingApi {
+        uploads: Cell<usize>,
+    }
+
+    impl UniformApi for CountingApi {
+        unsafe fn uniform_location(&self, _: GLuint, _: &str) -> GLint {
+            0
+        }
+
+        unsafe fn uniform_matrix4(&self, _: GLint, _: &Matrix4<f32>) {
+            self.uploads.set(self.uploads.get() + 1);
+        }
+
+        unsafe fn uniform_vec3(&self, _: GLint, _: &Vector3<f32>) {
+            self.uploads.set(self.uploads.get() + 1);
+        }
+    }
+
+    fn shader() -> Shader {
+        Shader {
+            id: 0,
+            uniforms: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn update_uniform_reports_changes() {
+        let mut shader = shader();
+
+        assert!(shader.update_uniform("view", Uniform::Mat4(Matrix4::one())));
+        assert!(!shader.update_uniform("view", Uniform::Mat4(Matrix4::one())));
+        assert!(shader.update_uniform("model", Uniform::Mat4(Matrix4::one())));
+        assert!(shader.update_uniform("view", Uniform::Mat4(Matrix4::<f32>::from_angle_y(Deg(90.0)))));
+    }
+
+    #[test]
+    fn setting_the_same_matrix_twice_uploads_once() {
+        let mut shader = shader();
+        let api = CountingApi { uploads: Cell::new(0) };
+        let rotation = Matrix4::<f32>::from_angle_y(Deg(90.0));
+
+        unsafe {
+            shader.set_mat4_with(&api, "model", &rotation);
+            shader.set_mat4_with(&api, "model", &rotation);
+        }
+        assert_eq!(api.uploads.get(), 1);
+
+        unsafe {
+            shader.set_mat4_with(&api, "model", &Matrix4::one());
+        }
+        assert_eq!(api.uploads.get(), 2);
+    }
+
+    #[test]
+    fn setting_the_same_vector_twice_uploads_once() {
+        let mut shader = shader();
+        let api = CountingApi { uploads: Cell::new(0) };
+        let light = Vector3::new(0.0, 1.0, 0.0);
+
+        unsafe {
+            shader.set_vec3_with(&api, "light", &light);
+            shader.set_vec3_with(&api, "light", &light);
+        }
+        assert_eq!(api.uploads.get(), 1);
+
+        // A matrix uniform of the same name is a different value.
+        unsafe {
+            shader.set_mat4_with(&api, "light", &Matrix4::one());
+        }
+        assert_eq!(api.uploads.get(), 2);
+    }
+}